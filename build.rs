@@ -2,14 +2,16 @@
 // binutils - build.rs
 
 use std::env;
-use std::ffi;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::path;
 use std::process;
 
 extern crate cc;
 
+#[cfg(feature = "system")]
+extern crate pkg_config;
+
 extern crate sha2;
 use sha2::{Digest, Sha256};
 
@@ -81,124 +83,937 @@ fn hash_file(filename: &str, hash_value: &str) -> bool {
     return format!("{:x}", hasher.result()) == hash_value;
 }
 
-fn build_binutils(version: &str, sha256sum: &str, output_directory: &str, targets: &str) {
-    // Build binutils from source
+fn strip_c_comments(source: &str) -> String {
+    // Remove /* */ comments, and keep newlines
+    let mut stripped = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+        match rest[start..].find("*/") {
+            Some(end) => {
+                let comment = &rest[start..start + end + 2];
+                stripped.extend(comment.chars().filter(|c| *c == '\n'));
+                rest = &rest[start + end + 2..];
+            }
+            None => {
+                rest = "";
+                break;
+            }
+        }
+    }
+    stripped.push_str(rest);
+    stripped
+}
 
-    let binutils_name = format!("binutils-{}", version);
-    let filename = format!("{}.tar.gz", binutils_name);
-    let directory_filename = format!("{}/{}", output_directory, filename);
+// A token of a bfd_mach_* value
+#[derive(Clone, Debug, PartialEq)]
+enum CToken {
+    Number(u64),
+    Char(char),
+    Identifier(String),
+    Symbol(&'static str),
+}
 
-    // Check if binutils is already built
-    if path::Path::new(&format!("{}/built/", output_directory)).exists() {
-        return;
+fn c_tokens(value: &str) -> Option<Vec<CToken>> {
+    // Split a bfd_mach_* value, and reject anything that is not a constant expression
+
+    let mut tokens = Vec::new();
+    let mut chars = value.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() {
+            let mut number = c.to_string();
+            while let Some(&n) = chars.peek() {
+                if n.is_ascii_alphanumeric() {
+                    number.push(n);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            // Remove the U and L suffixes
+            let number = number.trim_end_matches(|c| "uUlL".contains(c));
+            let value = if number.starts_with("0x") || number.starts_with("0X") {
+                u64::from_str_radix(&number[2..], 16).ok()?
+            } else if number.len() > 1 && number.starts_with('0') {
+                u64::from_str_radix(&number[1..], 8).ok()?
+            } else {
+                number.parse().ok()?
+            };
+            tokens.push(CToken::Number(value));
+        } else if c == '\'' {
+            // i.e. 'E' for bfd_mach_v850e
+            let literal = chars.next()?;
+            if !literal.is_ascii_graphic() || "'\\".contains(literal) || chars.next()? != '\'' {
+                return None;
+            }
+            tokens.push(CToken::Char(literal));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut identifier = c.to_string();
+            while let Some(&n) = chars.peek() {
+                if n.is_ascii_alphanumeric() || n == '_' {
+                    identifier.push(n);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            if !identifier.starts_with("bfd_mach_") {
+                return None;
+            }
+            tokens.push(CToken::Identifier(identifier));
+        } else if c == '<' && chars.peek() == Some(&'<') {
+            chars.next();
+            tokens.push(CToken::Symbol("<<"));
+        } else if c == '|' {
+            tokens.push(CToken::Symbol("|"));
+        } else if c == '(' {
+            tokens.push(CToken::Symbol("("));
+        } else if c == ')' {
+            tokens.push(CToken::Symbol(")"));
+        } else if !c.is_whitespace() {
+            return None;
+        }
     }
 
-    // Check if the tarball exists, or download it
-    if !path::Path::new(&filename).exists() {
-        execute_command(
-            "curl",
-            vec![
-                format!("https://ftp.gnu.org/gnu/binutils/{}", filename).as_str(),
-                "--output",
-                &directory_filename,
-            ],
-        );
+    match tokens.is_empty() {
+        true => None,
+        false => Some(tokens),
     }
+}
 
-    // Verify the SHA256 hash
-    if !hash_file(&directory_filename, sha256sum) {
+fn c_to_rust_expression(tokens: &[CToken]) -> String {
+    // Convert a bfd_mach_* value, character literals being u64 values too
+
+    let mut expression = String::new();
+    for token in tokens {
+        match *token {
+            CToken::Number(value) => expression.push_str(&value.to_string()),
+            CToken::Char(c) => expression.push_str(&format!("('{}' as u64)", c)),
+            CToken::Identifier(ref name) => expression.push_str(name),
+            CToken::Symbol(s) if s == "(" || s == ")" => expression.push_str(s),
+            CToken::Symbol(s) => expression.push_str(&format!(" {} ", s)),
+        }
+    }
+    expression
+}
+
+fn camel_case(name: &str) -> String {
+    // Convert a libbfd name to a Rust type name, i.e. x86_64_intel_syntax to X86_64IntelSyntax
+
+    let mut camel = String::new();
+    for part in name.split('_').filter(|p| !p.is_empty()) {
+        if camel.ends_with(|c: char| c.is_ascii_digit())
+            && part.starts_with(|c: char| c.is_ascii_digit())
+        {
+            camel.push('_');
+        }
+        let mut chars = part.chars();
+        camel.extend(chars.next().map(|c| c.to_ascii_uppercase()));
+        camel.push_str(chars.as_str());
+    }
+    camel
+}
+
+fn evaluate_c_tokens(tokens: &[CToken], values: &[(String, u64)]) -> Option<u64> {
+    // Compute a bfd_mach_* value, with the C precedence of << and |
+
+    fn or(tokens: &[CToken], position: &mut usize, values: &[(String, u64)]) -> Option<u64> {
+        let mut value = shift(tokens, position, values)?;
+        while tokens.get(*position) == Some(&CToken::Symbol("|")) {
+            *position += 1;
+            value |= shift(tokens, position, values)?;
+        }
+        Some(value)
+    }
+
+    fn shift(tokens: &[CToken], position: &mut usize, values: &[(String, u64)]) -> Option<u64> {
+        let mut value = primary(tokens, position, values)?;
+        while tokens.get(*position) == Some(&CToken::Symbol("<<")) {
+            *position += 1;
+            let bits = primary(tokens, position, values)?;
+            if bits >= 64 {
+                return None;
+            }
+            value <<= bits;
+        }
+        Some(value)
+    }
+
+    fn primary(tokens: &[CToken], position: &mut usize, values: &[(String, u64)]) -> Option<u64> {
+        let value = match *tokens.get(*position)? {
+            CToken::Number(value) => value,
+            CToken::Char(c) => c as u64,
+            CToken::Identifier(ref name) => values.iter().find(|v| v.0 == *name)?.1,
+            CToken::Symbol("(") => {
+                *position += 1;
+                let value = or(tokens, position, values)?;
+                if tokens.get(*position) != Some(&CToken::Symbol(")")) {
+                    return None;
+                }
+                value
+            }
+            CToken::Symbol(_) => return None,
+        };
+        *position += 1;
+        Some(value)
+    }
+
+    let mut position = 0;
+    let value = or(tokens, &mut position, values)?;
+    match position == tokens.len() {
+        true => Some(value),
+        false => None,
+    }
+}
+
+fn generate_definitions(bfd_header: &str, output_directory: &str) {
+    // Generate the bfd_arch_* and bfd_mach_* constants, and the Rust types using them, from bfd.h
+
+    let mut header = String::new();
+    File::open(bfd_header)
+        .and_then(|mut f| f.read_to_string(&mut header))
+        .expect(&format!("Can't read {}", bfd_header));
+    let header = strip_c_comments(&header.replace("\\\n", " "));
+
+    // Note: machines are defined after their architecture in enum bfd_architecture
+    let mut constants = Vec::new();
+    let mut architectures = Vec::new();
+    let mut machines: Vec<(String, Vec<CToken>, Option<usize>)> = Vec::new();
+    let mut arch_index = None;
+    let mut arch_done = false;
+    for line in header.lines() {
+        let line = line.trim();
+
+        // Note: the enum definition is followed by functions returning it
+        if !arch_done && line.trim_end_matches('{').trim() == "enum bfd_architecture" {
+            arch_index = Some(0);
+            continue;
+        }
+        if let Some(index) = arch_index {
+            let name = line.trim_end_matches(',').trim();
+            if line.starts_with('}') {
+                arch_index = None;
+                arch_done = true;
+            } else if name.starts_with("bfd_arch_")
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                constants.push(format!("pub const {}: u32 = {};", name, index));
+                architectures.push((name["bfd_arch_".len()..].to_string(), index, Vec::new()));
+                arch_index = Some(index + 1);
+                continue;
+            }
+        }
+
+        if line.starts_with("#define") {
+            let mut tokens = line["#define".len()..].trim().splitn(2, char::is_whitespace);
+            let name = tokens.next().unwrap_or("");
+            let value = tokens.next().unwrap_or("");
+            if !name.starts_with("bfd_mach_") || machines.iter().any(|m| m.0 == name) {
+                continue;
+            }
+            if let Some(tokens) = c_tokens(value) {
+                let arch = arch_index.and_then(|_| architectures.len().checked_sub(1));
+                machines.push((name.to_string(), tokens, arch));
+            }
+        }
+    }
+
+    // Compute the values, and drop the ones that use skipped definitions
+    let mut values: Vec<(String, u64)> = Vec::new();
+    loop {
+        let count = values.len();
+        for (name, tokens, _) in &machines {
+            if values.iter().all(|v| v.0 != *name) {
+                if let Some(value) = evaluate_c_tokens(tokens, &values) {
+                    values.push((name.clone(), value));
+                }
+            }
+        }
+        if values.len() == count {
+            break;
+        }
+    }
+    for (name, tokens, arch) in &machines {
+        let value = match values.iter().find(|v| v.0 == *name) {
+            Some(v) => v.1,
+            None => continue,
+        };
+        constants.push(format!(
+            "pub const {}: u64 = {};",
+            name,
+            c_to_rust_expression(tokens)
+        ));
+        if let Some(index) = *arch {
+            architectures[index].2.push((name.clone(), value));
+        }
+    }
+
+    if architectures.is_empty() {
         panic!(
             "\n\n  \
-             Incorrect hash value for {} !\n\n",
-            filename
+             No enum bfd_architecture found in {} !\n\n",
+            bfd_header
+        );
+    }
+
+    let content = format!(
+        "// Generated by build.rs from {}\n\n{}\n",
+        bfd_header,
+        constants.join("\n")
+    );
+    let output_filename = format!("{}/mach.rs", output_directory);
+    File::create(&output_filename)
+        .and_then(|mut f| f.write_all(content.as_bytes()))
+        .expect(&format!("Can't write {}", output_filename));
+
+    // Note: bfd_arch_unknown, bfd_arch_obscure and bfd_arch_last are not architectures
+    architectures.retain(|a| a.0 != "unknown" && a.0 != "obscure" && a.0 != "last");
+    generate_architectures(
+        bfd_header,
+        &architectures,
+        &format!("{}/arch.rs", output_directory),
+    );
+}
+
+fn machine_variants(arch: &str, machines: &[(String, u64)]) -> Vec<(String, String, u64)> {
+    // Name the machines of an architecture, i.e. bfd_mach_x86_64 becomes X86_64
+
+    let mut variants: Vec<(String, String, u64)> = Vec::new();
+    let mut numbers = Vec::new();
+    for (name, value) in machines {
+        // Note: the first definition of a number is used, the other ones are aliases
+        if numbers.contains(value) {
+            continue;
+        }
+
+        let full = &name["bfd_mach_".len()..];
+        let short = match full.starts_with(arch) {
+            true => full[arch.len()..].trim_start_matches('_'),
+            false => full,
+        };
+        let candidates = match short.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            true => vec![camel_case(short), camel_case(full)],
+            false => vec![camel_case(full)],
+        };
+        if let Some(variant) = candidates
+            .into_iter()
+            .find(|c| variants.iter().all(|v| v.0 != *c))
+        {
+            variants.push((variant, name.clone(), *value));
+            numbers.push(*value);
+        }
+    }
+
+    // The machine number is 0 when the file does not tell
+    if !numbers.contains(&0) && variants.iter().all(|v| v.0 != "Default") {
+        let description = "Machine number 0, when the file does not tell";
+        variants.push(("Default".to_string(), description.to_string(), 0));
+    }
+    variants
+}
+
+fn generate_architectures(
+    bfd_header: &str,
+    architectures: &[(String, u32, Vec<(String, u64)>)],
+    output_filename: &str,
+) {
+    // Generate the Architecture and Machine enums, and their conversions to libbfd numbers
+
+    let variant = |name: &str| format!("Architecture::{}", camel_case(name));
+    let machine = |name: &str| format!("Machine::{}", camel_case(name));
+    let machines: Vec<_> = architectures
+        .iter()
+        .map(|(name, _, machines)| match machines.is_empty() {
+            true => Vec::new(),
+            false => machine_variants(name, machines),
+        })
+        .collect();
+
+    let mut content = format!("// Generated by build.rs from {}\n\n", bfd_header);
+    content.push_str("/// Architectures of `enum bfd_architecture`\n");
+    content.push_str("#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]\n");
+    content.push_str("pub enum Architecture {\n");
+    for (name, _, _) in architectures {
+        content.push_str(&format!("    {},\n", camel_case(name)));
+    }
+    content.push_str("}\n\n");
+
+    content.push_str("/// All architectures, in the `enum bfd_architecture` order\n");
+    content.push_str("pub const ARCHITECTURES: &[Architecture] = &[\n");
+    for (name, _, _) in architectures {
+        content.push_str(&format!("    {},\n", variant(name)));
+    }
+    content.push_str("];\n\n");
+
+    content.push_str("impl Architecture {\n");
+    content.push_str("    /// Name in `enum bfd_architecture`, i.e. \"i386\"\n");
+    content.push_str("    pub fn name(self) -> &'static str {\n        match self {\n");
+    for (name, _, _) in architectures {
+        content.push_str(&format!("            {} => \"{}\",\n", variant(name), name));
+    }
+    content.push_str("        }\n    }\n\n");
+
+    content.push_str("    pub(crate) fn raw(self) -> u32 {\n        match self {\n");
+    for (name, number, _) in architectures {
+        content.push_str(&format!("            {} => {},\n", variant(name), number));
+    }
+    content.push_str("        }\n    }\n}\n\n");
+
+    // Architectures without bfd_mach_* definitions only use the machine number 0
+    for ((name, _, _), variants) in architectures.iter().zip(&machines) {
+        if variants.is_empty() {
+            continue;
+        }
+        content.push_str(&format!("/// Machines of bfd_arch_{}\n", name));
+        content.push_str("#[allow(clippy::enum_variant_names, clippy::upper_case_acronyms)]\n");
+        content.push_str("#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]\n");
+        content.push_str(&format!("pub enum {}Machine {{\n", camel_case(name)));
+        for (variant, description, _) in variants {
+            content.push_str(&format!("    /// {}\n    {},\n", description, variant));
+        }
+        content.push_str("}\n\n");
+
+        content.push_str(&format!(
+            "impl From<{0}Machine> for Machine {{\n    \
+             fn from(machine: {0}Machine) -> Machine {{\n        \
+             Machine::{0}(machine)\n    }}\n}}\n\n",
+            camel_case(name)
+        ));
+    }
+
+    content.push_str(
+        "/// A machine and its architecture, i.e. `Machine::I386(I386Machine::X86_64)`\n",
+    );
+    content.push_str("#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]\n");
+    content.push_str("pub enum Machine {\n");
+    for ((name, _, _), variants) in architectures.iter().zip(&machines) {
+        match variants.is_empty() {
+            true => content.push_str(&format!("    {},\n", camel_case(name))),
+            false => content.push_str(&format!("    {0}({0}Machine),\n", camel_case(name))),
+        }
+    }
+    content.push_str("}\n\n");
+
+    // Note: a machine is identified by both numbers, since mach values are reused
+    let mut architecture_arms = String::new();
+    let mut raw_arms = String::new();
+    let mut number_arms = String::new();
+    for ((name, arch, _), variants) in architectures.iter().zip(&machines) {
+        if variants.is_empty() {
+            architecture_arms.push_str(&format!(
+                "            {} => {},\n",
+                machine(name),
+                variant(name)
+            ));
+            raw_arms.push_str(&format!(
+                "            {} => ({}, 0),\n",
+                machine(name),
+                arch
+            ));
+            number_arms.push_str(&format!(
+                "            ({}, 0) => Some({}),\n",
+                arch,
+                machine(name)
+            ));
+            continue;
+        }
+
+        architecture_arms.push_str(&format!(
+            "            {}(_) => {},\n",
+            machine(name),
+            variant(name)
+        ));
+        for (machine_variant, _, value) in variants {
+            let value_machine = format!(
+                "{}({}Machine::{})",
+                machine(name),
+                camel_case(name),
+                machine_variant
+            );
+            raw_arms.push_str(&format!(
+                "            {} => ({}, {}),\n",
+                value_machine, arch, value
+            ));
+            number_arms.push_str(&format!(
+                "            ({}, {}) => Some({}),\n",
+                arch, value, value_machine
+            ));
+        }
+    }
+
+    content.push_str("impl Machine {\n");
+    content.push_str("    /// Return the architecture of the machine\n");
+    content.push_str("    pub fn architecture(self) -> Architecture {\n        match self {\n");
+    content.push_str(&architecture_arms);
+    content.push_str("        }\n    }\n\n");
+    content.push_str("    pub(crate) fn raw(self) -> (u32, u64) {\n        match self {\n");
+    content.push_str(&raw_arms);
+    content.push_str("        }\n    }\n\n");
+    content.push_str(
+        "    fn from_numbers(arch: u32, machine: u64) -> Option<Machine> {\n        \
+         match (arch, machine) {\n",
+    );
+    content.push_str(&number_arms);
+    content.push_str("            _ => None,\n        }\n    }\n}\n");
+
+    File::create(output_filename)
+        .and_then(|mut f| f.write_all(content.as_bytes()))
+        .expect(&format!("Can't write {}", output_filename));
+}
+
+fn source_version(directory: &str) -> Option<(String, String)> {
+    // Return the version from bfd/version.m4, and BFD_VERSION_DATE from bfd/version.h
+    let read = |name: &str| std::fs::read_to_string(format!("{}/bfd/{}", directory, name)).ok();
+
+    // i.e. m4_define([BFD_VERSION], [2.43.50])
+    let version = read("version.m4")?
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("m4_define([BFD_VERSION], ["))
+        .and_then(|l| l.split(']').next())
+        .map(|v| v.to_string())?;
+
+    let date = read("version.h")?
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("#define BFD_VERSION_DATE"))
+        .map(|d| d.trim().to_string())?;
+
+    Some((version, date))
+}
+
+fn newest_modification(directory: &path::Path) -> u64 {
+    // Return the most recent modification time of the files in a directory, in seconds
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+
+    let mut newest = 0;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        let modified = match metadata.is_dir() {
+            true => newest_modification(&entry.path()),
+            false => metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs()),
+        };
+        newest = newest.max(modified);
+    }
+    newest
+}
+
+fn binutils_sources(version: &str, sha256sum: &str, output_directory: &str) -> (String, String) {
+    // Return the binutils source directory, from BINUTILS_SRC_DIR, BINUTILS_TARBALL or ftp.gnu.org,
+    // and what identifies these sources
+
+    if let Ok(directory) = env::var("BINUTILS_SRC_DIR") {
+        if !path::Path::new(&format!("{}/configure", directory)).exists() {
+            panic!(
+                "\n\n  \
+                 BINUTILS_SRC_DIR ({}) is not a binutils source tree!\n\n",
+                directory
+            );
+        }
+        println!("cargo:rerun-if-changed={}", directory);
+
+        // helpers.c uses the internal headers of this release
+        let source_version = source_version(&directory);
+        match source_version.as_ref().and_then(|(v, _)| parse_version(v)) {
+            Some(v) if v >= MINIMUM_VERSION && Some(v) >= parse_version(version) => (),
+            _ => panic!(
+                "\n\n  \
+                 BINUTILS_SRC_DIR ({}) contains binutils {:?}, {} or newer is required!\n\n",
+                directory,
+                source_version.map(|(v, _)| v),
+                version
+            ),
+        }
+
+        // Note: edited sources are rebuilt
+        let (version, date) = source_version.unwrap_or_default();
+        let identity = format!(
+            "directory {} version {} date {} modified {}",
+            directory,
+            version,
+            date,
+            newest_modification(path::Path::new(&directory))
         );
+        return (directory, identity);
+    }
+
+    // Reuse the sources only if they were extracted from a verified tarball
+    let binutils_name = format!("binutils-{}", version);
+    let source_directory = format!("{}/{}", output_directory, binutils_name);
+    let stamp_filename = format!("{}.sha256", source_directory);
+    let identity = format!("sha256 {}", sha256sum);
+    let stamp = std::fs::read_to_string(&stamp_filename).unwrap_or_default();
+    if path::Path::new(&source_directory).exists() {
+        if stamp == sha256sum {
+            return (source_directory, identity);
+        }
+        std::fs::remove_dir_all(&source_directory).expect("Can't remove the binutils sources!");
     }
+    let _ = std::fs::remove_file(&stamp_filename);
+
+    // Use the local tarball, or download it
+    let tarball = match env::var("BINUTILS_TARBALL") {
+        Ok(tarball) => {
+            println!("cargo:rerun-if-changed={}", tarball);
+            tarball
+        }
+        Err(_) => {
+            let filename = format!("{}.tar.gz", binutils_name);
+            let tarball = format!("{}/{}", output_directory, filename);
+            if !path::Path::new(&tarball).exists() {
+                execute_command(
+                    "curl",
+                    vec![
+                        format!("https://ftp.gnu.org/gnu/binutils/{}", filename).as_str(),
+                        "--output",
+                        &tarball,
+                    ],
+                );
+            }
+            tarball
+        }
+    };
 
     // Check if the tarball exists after calling curl
-    if !path::Path::new(&directory_filename).exists() {
+    if !path::Path::new(&tarball).exists() {
         panic!(
             "\n\n  \
-             Can't download {} to {} using curl!\n\n",
-            filename, directory_filename
+             Can't find {} !\n\n",
+            tarball
         );
     }
 
-    // Call tar
-    change_dir(output_directory);
-    if !path::Path::new(&binutils_name).exists() {
-        execute_command("tar", vec!["xzf", &filename]);
-    }
-
-    // Calls commands to build binutils
-    if path::Path::new(&binutils_name).exists() {
-        change_dir(&binutils_name);
-        let prefix_arg = format!("--prefix={}/built/", output_directory);
-        execute_command(
-            "./configure",
-            vec![&prefix_arg, &format!("--enable-targets={}", targets)],
-        );
-    
-        // Set CFLAGS environment variable to include -fcommon
-        // https://github.com/easybuilders/easybuild-easyconfigs/issues/11988
-        std::env::set_var("CFLAGS", "-fcommon -g -O2");
-    
-        execute_command(
-            "./configure",
-            vec![&prefix_arg, &format!("--enable-targets={}", targets)],
-        );
-    
-        // For make commands, we need to ensure CFLAGS is passed
-        let make_env = vec![("CFLAGS", "-fcommon -g -O2")];
-    
-        execute_command_with_env("make", vec!["-j8"], &make_env);
-        execute_command_with_env("make", vec!["install"], &make_env);
-    
-        // Copy useful files
-        execute_command(
-            "cp",
-            vec![
-                "opcodes/config.h",
-                &format!("{}/built/include/", output_directory),
-            ],
+    // Verify the SHA256 hash
+    if !hash_file(&tarball, sha256sum) {
+        panic!(
+            "\n\n  \
+             Incorrect hash value for {} !\n\n",
+            tarball
         );
-        execute_command(
-            "cp",
-            vec![
-                "libiberty/libiberty.a",
-                &format!("{}/built/lib/", output_directory),
-            ],
+    }
+
+    // Call tar
+    execute_command("tar", vec!["xzf", &tarball, "-C", output_directory]);
+    if !path::Path::new(&source_directory).exists() {
+        panic!(
+            "\n\n  \
+             {} does not contain {} !\n\n",
+            tarball, binutils_name
         );
     }
+    std::fs::write(&stamp_filename, sha256sum).expect("Can't write the sources stamp file!");
+
+    (source_directory, identity)
+}
+
+// Targets enabled by each cargo feature, as defined in bfd/config.bfd
+const TARGET_FEATURES: &[(&str, &str)] = &[
+    ("CARGO_FEATURE_X86", "x86_64-linux,i686-linux"),
+    ("CARGO_FEATURE_ARM", "arm-linux-gnueabi,armeb-linux-gnueabi"),
+    ("CARGO_FEATURE_AARCH64", "aarch64-linux"),
+    ("CARGO_FEATURE_MIPS", "mips-linux,mips64-linux"),
+    ("CARGO_FEATURE_RISCV", "riscv64-linux"),
+];
+
+fn configured_targets() -> String {
+    // The TARGETS environment variable overrides cargo features
+    if let Some(targets) = env::var_os("TARGETS") {
+        return targets.into_string().expect("Invalid TARGETS content!");
+    }
+
+    // Build all targets when asked to, or when no target is selected
+    let targets: Vec<_> = TARGET_FEATURES
+        .iter()
+        .filter(|(feature, _)| env::var_os(feature).is_some())
+        .map(|(_, targets)| *targets)
+        .collect();
+    match env::var_os("CARGO_FEATURE_ALL_TARGETS").is_some() || targets.is_empty() {
+        true => "all".to_string(),
+        false => targets.join(","),
+    }
+}
+
+fn build_binutils(source_directory: &str, identity: &str, output_directory: &str, targets: &str) {
+    // Build binutils out-of-tree, and install it in output_directory/built/
+
+    // Check if binutils is already built from the same sources, with the same targets
+    let install_directory = format!("{}/built", output_directory);
+    let stamp_filename = format!("{}/stamp", install_directory);
+    let stamp = format!("{}\n{}\n", identity, targets);
+    let built_stamp = std::fs::read_to_string(&stamp_filename).unwrap_or_default();
+    if path::Path::new(&format!("{}/lib/libbfd.a", install_directory)).exists()
+        && built_stamp == stamp
+    {
+        return;
+    }
+
+    // Start from scratch, since configure caches the previous targets
+    let build_directory = format!("{}/build", output_directory);
+    let _ = std::fs::remove_dir_all(&build_directory);
+    let _ = std::fs::remove_dir_all(&install_directory);
+    std::fs::create_dir_all(&build_directory).expect("Can't create the build directory!");
+    change_dir(&build_directory);
+
+    // Set CFLAGS environment variable to include -fcommon
+    // https://github.com/easybuilders/easybuild-easyconfigs/issues/11988
+    let make_env = vec![("CFLAGS", "-fcommon -g -O2")];
+
+    let prefix_arg = format!("--prefix={}/", install_directory);
+    execute_command_with_env(
+        &format!("{}/configure", source_directory),
+        vec![&prefix_arg, &format!("--enable-targets={}", targets)],
+        &make_env,
+    );
+    execute_command_with_env("make", vec!["-j8"], &make_env);
+    execute_command_with_env("make", vec!["install"], &make_env);
+
+    // Copy useful files
+    execute_command(
+        "cp",
+        vec![
+            "opcodes/config.h",
+            &format!("{}/include/", install_directory),
+        ],
+    );
+    execute_command(
+        "cp",
+        vec![
+            "libiberty/libiberty.a",
+            &format!("{}/lib/", install_directory),
+        ],
+    );
+
+    std::fs::write(&stamp_filename, stamp).expect("Can't write the build stamp file!");
+}
+
+// Oldest binutils release providing the APIs used by helpers.c
+const MINIMUM_VERSION: (u32, u32) = (2, 39);
+
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    // Parse versions such as 2.43 or 2.43.50.20241221
+    let mut numbers = version.trim().split('.').map(|n| n.parse::<u32>());
+    match (numbers.next(), numbers.next()) {
+        (Some(Ok(major)), Some(Ok(minor))) => Some((major, minor)),
+        _ => None,
+    }
+}
+
+fn header_version(include_directories: &[String]) -> Option<(u32, u32)> {
+    // Retrieve the version from BFD_VERSION, i.e. 243000000 for 2.43, in bfdver.h
+    for directory in include_directories {
+        let mut content = String::new();
+        let filename = format!("{}/bfdver.h", directory);
+        if File::open(&filename)
+            .and_then(|mut f| f.read_to_string(&mut content))
+            .is_err()
+        {
+            continue;
+        }
+
+        for line in content.lines() {
+            let mut tokens = line.split_whitespace();
+            if let (Some("#define"), Some("BFD_VERSION"), Some(value)) =
+                (tokens.next(), tokens.next(), tokens.next())
+            {
+                let value = value.parse::<u32>().ok()?;
+                return Some((value / 100_000_000, value / 1_000_000 % 100));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(feature = "system")]
+fn pkg_config_binutils() -> Option<(Vec<String>, Option<(u32, u32)>)> {
+    // Find libbfd and libopcodes with pkg-config, which also prints the link flags
+    let bfd = pkg_config::Config::new().probe("libbfd").ok()?;
+    pkg_config::Config::new().probe("libopcodes").ok()?;
+
+    let include_directories = bfd
+        .include_paths
+        .iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect();
+    Some((include_directories, parse_version(&bfd.version)))
+}
+
+#[cfg(not(feature = "system"))]
+fn pkg_config_binutils() -> Option<(Vec<String>, Option<(u32, u32)>)> {
+    None
+}
+
+// Prefix used when BINUTILS_PREFIX is not set and pkg-config does not find libbfd
+const DEFAULT_PREFIX: &str = "/usr";
+
+// Debian multiarch directory name, i.e. x86_64-linux-gnu for x86_64-unknown-linux-gnu
+fn multiarch_name() -> Option<String> {
+    let target = env::var("TARGET").ok()?;
+    let parts: Vec<&str> = target.splitn(3, '-').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+
+    let arch = match parts[0] {
+        "i586" | "i686" => "i386",
+        arch if arch.starts_with("arm") => "arm",
+        arch => arch,
+    };
+    Some(format!("{}-{}", arch, parts[2]))
+}
+
+fn prefix_binutils(prefix: &str) -> (Vec<String>, Option<(u32, u32)>) {
+    // Use the libraries and the headers installed in a prefix
+    let mut library_directories = vec![format!("{}/lib", prefix), format!("{}/lib64", prefix)];
+    if let Some(name) = multiarch_name() {
+        library_directories.push(format!("{}/lib/{}", prefix, name));
+    }
+    library_directories.retain(|d| path::Path::new(d).is_dir());
+    if library_directories.is_empty() {
+        library_directories.push(format!("{}/lib", prefix));
+    }
+
+    for directory in &library_directories {
+        println!("cargo:rustc-link-search=native={}", directory);
+        // Note: only the tests and examples of this crate get the rpath, other binaries need
+        //       LD_LIBRARY_PATH when the prefix is not known by the dynamic linker
+        if prefix != DEFAULT_PREFIX {
+            println!("cargo:rustc-link-arg=-Wl,-rpath,{}", directory);
+        }
+    }
+    println!("cargo:rustc-link-lib=bfd");
+    println!("cargo:rustc-link-lib=opcodes");
+    (vec![format!("{}/include", prefix)], None)
+}
+
+fn system_binutils() -> Vec<String> {
+    // Find an installed libbfd and libopcodes, and return the include directories
+
+    let (include_directories, version) = match env::var("BINUTILS_PREFIX") {
+        Ok(prefix) => prefix_binutils(&prefix),
+        Err(_) => match pkg_config_binutils() {
+            Some(found) => found,
+            // Note: most distributions do not ship pkg-config files for libbfd
+            None => prefix_binutils(DEFAULT_PREFIX),
+        },
+    };
+
+    let version = version.or_else(|| header_version(&include_directories));
+    match version {
+        Some(v) if v >= MINIMUM_VERSION => (),
+        Some(v) => panic!(
+            "\n\n  \
+             binutils {}.{} is too old, {}.{} or newer is required!\n\n",
+            v.0, v.1, MINIMUM_VERSION.0, MINIMUM_VERSION.1
+        ),
+        None => panic!(
+            "\n\n  \
+             Can't find the binutils version in bfdver.h from {:?} !\n\n",
+            include_directories
+        ),
+    }
+
+    include_directories
+}
+
+fn find_header(include_directories: &[String], name: &str) -> Option<String> {
+    include_directories
+        .iter()
+        .map(|d| format!("{}/{}", d, name))
+        .find(|f| path::Path::new(f).exists())
 }
 
 fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+
+    // Rebuild when the configuration changes
+    for variable in &["TARGETS", "BINUTILS_PREFIX", "BINUTILS_TARBALL", "BINUTILS_SRC_DIR"] {
+        println!("cargo:rerun-if-env-changed={}", variable);
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/helpers.c");
+
+    // Link against the installed binutils
+    if env::var_os("CARGO_FEATURE_SYSTEM").is_some() {
+        // The installed libbfd can't be rebuilt with other targets
+        let ignored: Vec<_> = TARGET_FEATURES
+            .iter()
+            .map(|(feature, _)| *feature)
+            .chain(vec!["CARGO_FEATURE_ALL_TARGETS", "TARGETS"])
+            .filter(|variable| env::var_os(variable).is_some())
+            .collect();
+        if !ignored.is_empty() {
+            println!(
+                "cargo:warning=the system feature uses the targets of the installed libbfd, {:?} \
+                 are ignored",
+                ignored
+            );
+        }
+
+        let include_directories = system_binutils();
+
+        let bfd_header = find_header(&include_directories, "bfd.h")
+            .expect("Can't find bfd.h, is binutils-dev installed?");
+        generate_definitions(&bfd_header, &out_dir);
+
+        // Note: config.h and elf-bfd.h are usually not installed
+        let mut build = cc::Build::new();
+        build.file("src/helpers.c");
+        for directory in &include_directories {
+            build.include(directory);
+        }
+        match find_header(&include_directories, "elf-bfd.h") {
+            Some(_) => {
+                build.define("HAVE_ELF_BFD_H", None);
+            }
+            None => println!(
+                "cargo:warning=elf-bfd.h not found in {:?}, symbol sizes, ELF flags and \
+                 MIPS16/microMIPS symbols will be reported as 0",
+                include_directories
+            ),
+        }
+        build.compile("helpers");
+        return;
+    }
+
     let version = "2.43";
     let sha256 = "025c436d15049076ebe511d29651cc4785ee502965a8839936a65518582bdd64";
 
     // Retrieve targets to build
-    let targets_var = match env::var_os("TARGETS") {
-        Some(dir) => dir,
-        None => ffi::OsString::from("all"),
-    };
-    let targets = targets_var
-        .as_os_str()
-        .to_str()
-        .expect("Invalid TARGETS content!");
+    let targets = &configured_targets();
+    println!("cargo:rustc-env=BINUTILS_TARGETS={}", targets);
 
     // Get the current working directory
     let current_dir = env::current_dir().unwrap();
 
-    // Where binutils will be built
-    let out_directory = format!("{}/target", current_dir.to_str().unwrap());
+    // Where binutils will be built, so that cargo caches it with the crate
+    let out_directory = format!("{}/binutils", out_dir);
+    std::fs::create_dir_all(&out_directory).expect("Can't create the output directory!");
 
     // Build binutils
-    build_binutils(version, sha256, &out_directory, targets);
+    let (source_directory, identity) = binutils_sources(version, sha256, &out_directory);
+    build_binutils(&source_directory, &identity, &out_directory, targets);
+
+    // Generate the architecture and machine definitions of the built libbfd
+    generate_definitions(&format!("{}/built/include/bfd.h", out_directory), &out_dir);
 
     // Build our C helpers
     change_dir(current_dir.to_str().unwrap());
+    // Note: elf-bfd.h is not installed, it is used from the binutils sources
     cc::Build::new()
         .file("src/helpers.c")
         .include(format!("{}/built/include/", out_directory))
+        .include(format!("{}/bfd/", source_directory))
+        .include(format!("{}/include/", source_directory))
+        .define("HAVE_CONFIG_H", None)
+        .define("HAVE_ELF_BFD_H", None)
         .compile("helpers");
 
     // Locally compiled binutils libraries path
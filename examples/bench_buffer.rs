@@ -0,0 +1,91 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - bench_buffer.rs
+
+extern crate binutils;
+use binutils::opcodes::Endianness;
+use binutils::utils;
+
+use std::env;
+use std::time::Instant;
+
+fn bench_iterator(buffer: &[u8]) {
+    let start = Instant::now();
+
+    let mut info = match utils::disassemble_buffer("i386:x86-64", buffer, 0, Endianness::Little) {
+        Ok(i) => i,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+
+    // Disassemble one instruction at a time
+    let mut counter = 0;
+    while let Some(Ok(_)) = info.disassemble() {
+        counter += 1;
+    }
+
+    println!(
+        "disassemble()       - {} instructions in {:?}",
+        counter,
+        start.elapsed()
+    );
+}
+
+fn bench_batch(buffer: &[u8]) {
+    let start = Instant::now();
+
+    let instructions = match utils::disassemble_all("i386:x86-64", buffer, 0, Endianness::Little) {
+        Ok(i) => i,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+
+    println!(
+        "disassemble_all()   - {} instructions in {:?}",
+        instructions.len(),
+        start.elapsed()
+    );
+}
+
+fn bench_arena(buffer: &[u8]) {
+    let start = Instant::now();
+
+    let batch = match utils::disassemble_batch("i386:x86-64", buffer, 0, Endianness::Little) {
+        Ok(b) => b,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+
+    println!(
+        "disassemble_batch() - {} instructions in {:?}",
+        batch.len(),
+        start.elapsed()
+    );
+}
+
+fn main() {
+    // Size of the buffer in MB, i.e. cargo run --release --example bench_buffer 16
+    let size = env::args()
+        .nth(1)
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(4);
+
+    // push rbp; mov rbp,rsp; mov eax,DWORD PTR [rbp-0x4]; nop; pop rbp; ret
+    let pattern = [0x55, 0x48, 0x89, 0xe5, 0x8b, 0x45, 0xfc, 0x90, 0x5d, 0xc3];
+    let buffer: Vec<u8> = pattern
+        .iter()
+        .cycle()
+        .take(size * 1024 * 1024 / pattern.len() * pattern.len())
+        .cloned()
+        .collect();
+
+    println!("Disassembling {} MB", size);
+    bench_iterator(&buffer);
+    bench_batch(&buffer);
+    bench_arena(&buffer);
+}
@@ -1,21 +1,11 @@
 // Guillaume Valadon <guillaume@valadon.net>
 // binutils - test_binary.rs
 
-extern crate libc;
-use libc::{c_ulong, uintptr_t};
-
 extern crate binutils;
 use binutils::bfd;
 use binutils::instruction;
 use binutils::opcodes::DisassembleInfo;
-use binutils::utils;
-
-extern "C" fn change_address(addr: c_ulong, _info: *const uintptr_t) {
-    // Example of C callback that modifies an address used by an instruction
-
-    // Format the address and copy it to the buffer
-    utils::opcode_buffer_append(&format!("0x{:x}", addr));
-}
+use binutils::symbolizer::BfdSymbolizer;
 
 fn test_ls(max_instructions: Option<u32>) {
     println!("From an ELF");
@@ -55,7 +45,7 @@ fn test_ls(max_instructions: Option<u32>) {
     };
 
     // Create a disassemble_info structure
-    let info = match DisassembleInfo::new() {
+    let mut info = match DisassembleInfo::new() {
         Ok(i) => i,
         Err(e) => {
             println!("{}", e);
@@ -63,7 +53,7 @@ fn test_ls(max_instructions: Option<u32>) {
         }
     };
 
-    // Configure the disassemble_info structure
+    // Configure the disassemble_info structure, and its symbols
     match info.configure(section, bfd) {
         Ok(_) => (),
         Err(e) => {
@@ -71,10 +61,19 @@ fn test_ls(max_instructions: Option<u32>) {
             return;
         }
     };
-    match info.set_print_address_func(change_address) {
+
+    // Print addresses used by instructions relative to symbols
+    let symbolizer = match BfdSymbolizer::new(bfd) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("Error with BfdSymbolizer::new() - {}", e);
+            return;
+        }
+    };
+    match info.set_symbolizer(symbolizer) {
         Ok(_) => (),
         Err(e) => {
-            println!("Error set_print_address_func() - {}", e);
+            println!("Error set_symbolizer() - {}", e);
             return;
         }
     };
@@ -94,10 +93,10 @@ fn test_ls(max_instructions: Option<u32>) {
             return;
         }
     };
-    let section_size = match section.get_size() {
-        Ok(a) => a,
-        Err(e) => {
-            println!("Error with get_size() - {}", e);
+    let section_end = match (section.get_vma(), section.get_size()) {
+        (Ok(vma), Ok(size)) => vma + size,
+        (Err(e), _) | (_, Err(e)) => {
+            println!("Error with get_vma() or get_size() - {}", e);
             return;
         }
     };
@@ -117,7 +116,7 @@ fn test_ls(max_instructions: Option<u32>) {
         pc += length;
         counter += 1;
 
-        if !(length > 0 && pc <= section_size) {
+        if !(length > 0 && pc < section_end) {
             break;
         }
 
@@ -127,6 +126,44 @@ fn test_ls(max_instructions: Option<u32>) {
     }
 }
 
+fn test_ls_all(max_instructions: usize) {
+    println!("From an ELF (all code sections)");
+
+    let bfd = match bfd::Bfd::openr("/bin/ls", "elf64-x86-64") {
+        Ok(b) => b,
+        Err(e) => {
+            println!("Error with openr() - {}", e);
+            return;
+        }
+    };
+
+    match bfd.check_format(bfd::BfdFormat::bfd_object) {
+        Ok(_) => (),
+        Err(e) => {
+            println!("Error with check_format() - {}", e);
+            return;
+        }
+    };
+
+    let disassembly = match bfd.disassemble_all() {
+        Ok(d) => d,
+        Err(e) => {
+            println!("Error with disassemble_all() - {}", e);
+            return;
+        }
+    };
+
+    // Disassemble every code section, like objdump -d
+    for (section, instruction) in disassembly.take(max_instructions) {
+        let name = section.get_name().unwrap_or_default();
+        match instruction.error {
+            Some(e) => println!("{} - {}", name, e),
+            None => println!("{} {}", name, instruction),
+        }
+    }
+}
+
 fn main() {
     test_ls(Some(65535));
+    test_ls_all(65535);
 }
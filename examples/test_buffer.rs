@@ -5,7 +5,7 @@ extern crate binutils;
 use binutils::bfd;
 use binutils::instruction;
 use binutils::instruction::Instruction;
-use binutils::opcodes::DisassembleInfo;
+use binutils::opcodes::{DisassembleInfo, Endianness};
 use binutils::utils;
 
 fn test_buffer_full(arch_name: &str, buffer: Vec<u8>, offset: u64) {
@@ -19,9 +19,9 @@ fn test_buffer_full(arch_name: &str, buffer: Vec<u8>, offset: u64) {
         return;
     }
 
-    // Retrieve bfd_arch and bfd_mach from the architecture name
-    let bfd_arch_mach = match bfd.set_arch_mach(arch_name) {
-        Ok(arch_mach) => arch_mach,
+    // Retrieve the machine from the architecture name
+    let machine = match bfd.set_arch_mach(arch_name) {
+        Ok(m) => m,
         Err(e) => {
             println!("Error with set_arch_mach() - {}", e);
             return;
@@ -29,7 +29,7 @@ fn test_buffer_full(arch_name: &str, buffer: Vec<u8>, offset: u64) {
     };
 
     // Construct disassembler_ftype class
-    let disassemble = match bfd.raw_disassembler(bfd_arch_mach.0, false, bfd_arch_mach.1) {
+    let disassemble = match bfd.raw_disassembler(machine, false) {
         Ok(d) => d,
         Err(e) => {
             println!("Error with raw_disassembler() - {}", e);
@@ -47,7 +47,7 @@ fn test_buffer_full(arch_name: &str, buffer: Vec<u8>, offset: u64) {
     };
 
     // Configure the disassemble_info structure
-    match info.configure_buffer(bfd_arch_mach.0, bfd_arch_mach.1, &buffer, offset) {
+    match info.configure_buffer(machine, &buffer, offset, Endianness::Little) {
         Ok(_) => (),
         Err(e) => {
             println!("configure_buffer() - {}", e);
@@ -102,7 +102,7 @@ fn test_buffer_compact(arch_name: &str, buffer: Vec<u8>, offset: u64) {
     };
 
     // Configure the disassemble_info structure
-    match info.init_buffer(&buffer, bfd, offset) {
+    match info.init_buffer(&buffer, bfd, offset, Endianness::Little) {
         Ok(_) => (),
         Err(e) => {
             println!("init_buffer() - {}", e);
@@ -130,7 +130,7 @@ fn test_buffer_utils(arch_name: &str, buffer: Vec<u8>, offset: u64) {
     println!("---");
     println!("From a buffer (binutils::utils) - {}", arch_name);
 
-    let mut info = match utils::disassemble_buffer(arch_name, &buffer, offset) {
+    let mut info = match utils::disassemble_buffer(arch_name, &buffer, offset, Endianness::Little) {
         Ok(i) => i,
         Err(e) => {
             println!("{}", e);
@@ -178,7 +178,8 @@ fn test_buffer_iter(arch_name: &str, buffer: Vec<u8>, offset: u64) {
     };
 
     // Disassemble the buffer using an iterator
-    for instruction in Instruction::from_buffer(&mut info, bfd, &buffer, offset) {
+    for instruction in Instruction::from_buffer(&mut info, bfd, &buffer, offset, Endianness::Little)
+    {
         println!("{}", instruction);
     }
 }
@@ -0,0 +1,9 @@
+// AArch64 code followed by data, used by the Rust tests
+	.text
+
+	.globl aarch64_function
+	.type aarch64_function, %function
+aarch64_function:
+	mov x0, #1
+	ret
+	.word 0xdeadbeef
@@ -0,0 +1,21 @@
+// Mixed ARM and Thumb code with a literal pool, used by the Rust tests
+	.syntax unified
+	.arch armv7-a
+	.text
+
+	.arm
+	.globl arm_function
+	.type arm_function, %function
+arm_function:
+	ldr r0, =0xdeadbeef
+	blx thumb_function
+	bx lr
+	.ltorg
+
+	.thumb
+	.globl thumb_function
+	.type thumb_function, %function
+	.thumb_func
+thumb_function:
+	movs r0, #1
+	bx lr
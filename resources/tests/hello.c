@@ -0,0 +1,23 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - test binary used by the Rust tests
+
+#include <stdio.h>
+
+int square(int value)
+{
+    return value * value;
+}
+
+int sum_squares(int count)
+{
+    int total = 0;
+    for (int i = 0; i < count; i++)
+        total += square(i);
+    return total;
+}
+
+int main(int argc, char **argv)
+{
+    printf("%d\n", sum_squares(argc + 3));
+    return 0;
+}
@@ -0,0 +1,19 @@
+# x86-64 code with a symbol in the middle of an instruction, used by the Rust tests
+	.text
+
+	.globl straddle
+	.type straddle, @function
+straddle:
+	# Note: the nops move the symbol to a different chunk in disassemble_parallel()
+	.rept 0x1000
+	nop
+	.endr
+	movabs $0xb848909000000000, %rax
+	ret
+	.rept 8
+	nop
+	.endr
+
+	# Decoding from there gives nop; nop; movabs, which overlaps the ret
+	.globl middle
+	.set middle, straddle + 0x1006
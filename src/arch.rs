@@ -0,0 +1,240 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - arch.rs
+
+use libc::{c_char, c_uint, c_ulong};
+
+use std::collections::HashSet;
+use std::ffi::{CStr, CString};
+
+use bfd::{arch_list, BfdRaw};
+use helpers;
+use Error;
+
+extern "C" {
+    fn bfd_scan_arch(string: *const c_char) -> *const c_uint;
+
+    fn bfd_lookup_arch(arch: c_uint, machine: c_ulong) -> *const c_uint;
+
+    pub(crate) fn bfd_get_arch_info(bfd: *const BfdRaw) -> *const c_uint;
+}
+
+// Generated by build.rs from the bfd.h header of the built binutils
+include!(concat!(env!("OUT_DIR"), "/arch.rs"));
+
+impl Architecture {
+    /// Return the machine used when only the architecture is known
+    pub fn default_machine(self) -> Result<Machine, Error> {
+        ArchInfo::lookup(self.raw(), 0)?.machine()
+    }
+
+    /// Return the machine of this architecture with this libbfd number
+    pub fn machine(self, mach: u64) -> Result<Machine, Error> {
+        Machine::from_numbers(self.raw(), mach).ok_or_else(|| {
+            Error::CommonError(format!("Unknown {} machine ({})!", self.name(), mach))
+        })
+    }
+
+    /// Return all machines of this architecture supported by libbfd
+    pub fn machines(self) -> Result<Vec<Machine>, Error> {
+        let arch = self.raw();
+        Ok(arch_infos()
+            .iter()
+            .filter(|info| info.arch == arch)
+            .filter_map(|info| info.machine().ok())
+            .collect())
+    }
+}
+
+impl Machine {
+    pub(crate) fn from_raw(arch: u32, mach: u64) -> Result<Machine, Error> {
+        Machine::from_numbers(arch, mach)
+            .ok_or_else(|| Error::CommonError(format!("Unknown machine ({}, {})!", arch, mach)))
+    }
+
+    /// Find a machine from its name, i.e. "i386:x86-64"
+    pub fn from_name(name: &str) -> Result<Machine, Error> {
+        ArchInfo::scan(name)?.machine()
+    }
+
+    /// Return the libbfd machine number, i.e. bfd_mach_x86_64
+    pub fn mach(self) -> u64 {
+        self.raw().1
+    }
+
+    pub fn arch_info(self) -> Result<ArchInfo, Error> {
+        let (arch, mach) = self.raw();
+        ArchInfo::lookup(arch, mach)
+    }
+}
+
+/// Description of a machine, from a libbfd bfd_arch_info structure
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchInfo {
+    pub arch: u32,
+    pub mach: u64,
+    pub arch_name: String,
+    pub printable_name: String,
+    pub bits_per_word: u32,
+    pub bits_per_address: u32,
+    pub bits_per_byte: u32,
+    // The machine used when only the architecture is known
+    pub is_default: bool,
+    pub section_align_power: u32,
+}
+
+impl ArchInfo {
+    pub(crate) fn from_raw(arch_info: *const c_uint) -> Result<ArchInfo, Error> {
+        if arch_info.is_null() {
+            return Err(Error::BfdError(0, "architecture not found!".to_string()));
+        }
+
+        let to_string = |s: *const c_char| match s.is_null() {
+            true => Ok(String::new()),
+            false => unsafe { CStr::from_ptr(s) }.to_str().map(|s| s.to_string()),
+        };
+
+        unsafe {
+            Ok(ArchInfo {
+                arch: helpers::get_arch(arch_info),
+                mach: helpers::get_mach(arch_info),
+                arch_name: to_string(helpers::get_arch_info_arch_name(arch_info))?,
+                printable_name: to_string(helpers::get_arch_info_printable_name(arch_info))?,
+                bits_per_word: helpers::get_arch_info_bits_per_word(arch_info),
+                bits_per_address: helpers::get_arch_info_bits_per_address(arch_info),
+                bits_per_byte: helpers::get_arch_info_bits_per_byte(arch_info),
+                is_default: helpers::is_arch_info_default(arch_info),
+                section_align_power: helpers::get_arch_info_section_align_power(arch_info),
+            })
+        }
+    }
+
+    /// Find a machine from its name, i.e. "i386:x86-64"
+    pub fn scan(name: &str) -> Result<ArchInfo, Error> {
+        let name_cstring = CString::new(name)?;
+        ArchInfo::from_raw(unsafe { bfd_scan_arch(name_cstring.as_ptr()) })
+    }
+
+    /// Find a machine from its numbers, 0 being the default machine of the architecture
+    pub fn lookup(arch: u32, mach: u64) -> Result<ArchInfo, Error> {
+        ArchInfo::from_raw(unsafe { bfd_lookup_arch(arch, mach) })
+    }
+
+    pub fn machine(&self) -> Result<Machine, Error> {
+        Machine::from_raw(self.arch, self.mach)
+    }
+
+    /// Return all machines of this architecture
+    pub fn machines(&self) -> Vec<ArchInfo> {
+        arch_infos()
+            .into_iter()
+            .filter(|info| info.arch == self.arch)
+            .collect()
+    }
+}
+
+/// Return all machines supported by libbfd
+pub fn arch_infos() -> Vec<ArchInfo> {
+    // Note: several names may scan to the same numbers, bfd_lookup_arch() gives the machine
+    let mut seen = HashSet::new();
+    arch_list()
+        .iter()
+        .filter_map(|name| ArchInfo::scan(name).ok())
+        .filter_map(|info| ArchInfo::lookup(info.arch, info.mach).ok())
+        .filter(|info| seen.insert((info.arch, info.mach)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_arch_info() {
+        use arch::ArchInfo;
+        use utils;
+
+        assert!(ArchInfo::scan("unknown").is_err());
+        if !utils::has_arch("i386:x86-64") {
+            return;
+        }
+
+        let info = ArchInfo::scan("i386:x86-64").unwrap();
+        assert_eq!(info.arch_name, "i386");
+        assert_eq!(info.printable_name, "i386:x86-64");
+        assert_eq!(info.bits_per_word, 64);
+        assert_eq!(info.bits_per_address, 64);
+        assert_eq!(info.bits_per_byte, 8);
+        assert_eq!(ArchInfo::lookup(info.arch, info.mach).unwrap(), info);
+
+        let machines = info.machines();
+        assert!(machines.contains(&info));
+        assert!(machines.iter().any(|m| m.printable_name == "i386"));
+        assert_eq!(machines.iter().filter(|m| m.is_default).count(), 1);
+        assert!(ArchInfo::lookup(info.arch, 0).unwrap().is_default);
+    }
+
+    #[test]
+    fn test_arch_infos() {
+        use arch;
+
+        let infos = arch::arch_infos();
+        assert!(!infos.is_empty());
+        for info in &infos {
+            assert_eq!(arch::ArchInfo::lookup(info.arch, info.mach).unwrap(), *info);
+        }
+
+        // Each machine is listed once
+        let mut machines: Vec<_> = infos.iter().map(|i| (i.arch, i.mach)).collect();
+        machines.sort_unstable();
+        machines.dedup();
+        assert_eq!(machines.len(), infos.len());
+    }
+
+    #[test]
+    fn test_architecture() {
+        use arch::{ArchInfo, Architecture, I386Machine, Machine, MipsMachine, ARCHITECTURES};
+        use mach;
+        use utils;
+
+        if !utils::has_arch("i386:x86-64") {
+            return;
+        }
+
+        let info = ArchInfo::scan("i386:x86-64").unwrap();
+        let machine = Machine::from_name("i386:x86-64").unwrap();
+        assert_eq!(machine, Machine::I386(I386Machine::X86_64));
+        assert_eq!(machine, Machine::from(I386Machine::X86_64));
+        assert_eq!(machine.architecture(), Architecture::I386);
+        assert_eq!(machine.raw(), (info.arch, info.mach));
+        assert_eq!(machine.arch_info().unwrap().printable_name, "i386:x86-64");
+        assert_eq!(Architecture::I386.machine(machine.mach()).unwrap(), machine);
+        assert!(Architecture::I386.machines().unwrap().contains(&machine));
+        assert!(
+            Architecture::I386
+                .default_machine()
+                .unwrap()
+                .arch_info()
+                .unwrap()
+                .is_default
+        );
+
+        // Architectures are generated from enum bfd_architecture
+        assert!(ARCHITECTURES.windows(2).all(|a| a[0].raw() < a[1].raw()));
+        assert_eq!(Architecture::I386.raw(), info.arch);
+        assert_eq!(Architecture::I386.name(), "i386");
+        assert_eq!(Architecture::I386.raw(), mach::bfd_arch_i386);
+        assert_eq!(machine.mach(), mach::bfd_mach_x86_64);
+
+        // Character literals are machine numbers too
+        assert_eq!(mach::bfd_mach_v850e, 'E' as u64);
+        assert_eq!(mach::bfd_mach_m32rx, 'x' as u64);
+
+        // Machine numbers are only valid for their architecture
+        if utils::has_arch("mips:16") {
+            let mips16 = Machine::from_name("mips:16").unwrap();
+            assert_eq!(mips16, Machine::Mips(MipsMachine::Mips16));
+            assert_eq!(mips16.architecture(), Architecture::Mips);
+        }
+        let micromips = Machine::Mips(MipsMachine::Micromips);
+        assert!(Architecture::I386.machine(micromips.mach()).is_err());
+        assert!(Machine::from_raw(info.arch, micromips.mach()).is_err());
+    }
+}
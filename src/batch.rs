@@ -0,0 +1,236 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - batch.rs
+
+use std::fmt;
+use std::fmt::Write;
+
+use helpers;
+use instruction::{Instruction, InstructionType};
+
+// An instruction whose text, bytes and addresses are stored in the batch buffers
+// Note: they start where the previous instruction ends
+struct Entry {
+    offset: u64,
+    length: u64,
+    text_end: usize,
+    bytes_end: usize,
+    addresses_end: usize,
+    insn_type: Option<InstructionType>,
+    target: Option<u64>,
+    branch_delay: u32,
+}
+
+/// Instructions disassembled at once, without allocating memory for each of them
+#[derive(Default)]
+pub struct InstructionBatch {
+    text: String,
+    bytes: Vec<u8>,
+    addresses: Vec<u64>,
+    entries: Vec<Entry>,
+}
+
+/// An instruction borrowed from an InstructionBatch
+#[derive(Clone, Copy, Debug)]
+pub struct InstructionRef<'a> {
+    pub offset: u64,
+    pub length: u64,
+    pub opcode: &'a str,
+    pub bytes: &'a [u8],
+    pub addresses: &'a [u64],
+    pub insn_type: Option<InstructionType>,
+    pub target: Option<u64>,
+    pub branch_delay: u32,
+}
+
+impl<'a> fmt::Display for InstructionRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:X} {}", self.offset, self.opcode)
+    }
+}
+
+impl<'a> InstructionRef<'a> {
+    /// Return true if the instruction is a jump, a call or a return
+    pub fn is_branch(&self) -> bool {
+        self.insn_type.map(|t| t.is_branch()).unwrap_or(false)
+    }
+
+    /// Copy the instruction, i.e. to keep it after the batch is dropped
+    pub fn to_instruction(&self) -> Instruction<'static> {
+        let mut instruction = Instruction::empty_with_error(None);
+        instruction.offset = self.offset;
+        instruction.length = self.length;
+        instruction.opcode = self.opcode.to_string();
+        instruction.insn_type = self.insn_type;
+        instruction.target = self.target;
+        instruction.branch_delay = self.branch_delay;
+        instruction.set_bytes(self.bytes.to_vec());
+        instruction.set_addresses(self.addresses.to_vec());
+        instruction
+    }
+}
+
+impl InstructionBatch {
+    // Reserve memory for a buffer of the given size
+    pub(crate) fn with_buffer_size(size: u64) -> InstructionBatch {
+        // Note: x86-64 instructions use 4 bytes and 24 characters on average
+        let count = (size / 4) as usize;
+        InstructionBatch {
+            text: String::with_capacity(count * 24),
+            bytes: Vec::with_capacity(size as usize),
+            addresses: Vec::new(),
+            entries: Vec::with_capacity(count),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<InstructionRef<'_>> {
+        let entry = self.entries.get(index)?;
+        let (text_start, bytes_start, addresses_start) = match index {
+            0 => (0, 0, 0),
+            _ => {
+                let previous = &self.entries[index - 1];
+                (
+                    previous.text_end,
+                    previous.bytes_end,
+                    previous.addresses_end,
+                )
+            }
+        };
+
+        Some(InstructionRef {
+            offset: entry.offset,
+            length: entry.length,
+            opcode: &self.text[text_start..entry.text_end],
+            bytes: &self.bytes[bytes_start..entry.bytes_end],
+            addresses: &self.addresses[addresses_start..entry.addresses_end],
+            insn_type: entry.insn_type,
+            target: entry.target,
+            branch_delay: entry.branch_delay,
+        })
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            batch: self,
+            index: 0,
+        }
+    }
+
+    // Move the text of the last disassembled instruction, and return it
+    pub(crate) fn push_opcode(&mut self) -> &str {
+        let start = self.text.len();
+        helpers::CURRENT_OPCODE.with(|opcode| {
+            let mut opcode = opcode.borrow_mut();
+            self.text.push_str(&opcode);
+            opcode.clear();
+        });
+        &self.text[start..]
+    }
+
+    // Describe undecodable bytes with a .byte directive
+    pub(crate) fn push_data(&mut self, bytes: &[u8]) {
+        self.text.push_str(".byte ");
+        for (index, byte) in bytes.iter().enumerate() {
+            let separator = if index == 0 { "" } else { ", " };
+            let _ = write!(self.text, "{}0x{:02x}", separator, byte);
+        }
+    }
+
+    // Store the bytes and the addresses of the current instruction, then the instruction itself
+    pub(crate) fn push(
+        &mut self,
+        offset: u64,
+        bytes: &[u8],
+        addresses: &[u64],
+        insn_type: Option<InstructionType>,
+        target: Option<u64>,
+        branch_delay: u32,
+    ) {
+        self.bytes.extend_from_slice(bytes);
+        self.addresses.extend_from_slice(addresses);
+        self.entries.push(Entry {
+            offset,
+            length: bytes.len() as u64,
+            text_end: self.text.len(),
+            bytes_end: self.bytes.len(),
+            addresses_end: self.addresses.len(),
+            insn_type,
+            target,
+            branch_delay,
+        });
+    }
+}
+
+/// Iterator over the instructions of an InstructionBatch
+pub struct Iter<'a> {
+    batch: &'a InstructionBatch,
+    index: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = InstructionRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let instruction = self.batch.get(self.index)?;
+        self.index += 1;
+        Some(instruction)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.batch.len() - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> IntoIterator for &'a InstructionBatch {
+    type Item = InstructionRef<'a>;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_batch() {
+        use batch::InstructionBatch;
+        use instruction::InstructionType;
+
+        let mut batch = InstructionBatch::with_buffer_size(4);
+        batch.push_data(&[0x06]);
+        batch.push(0x1000, &[0x06], &[], None, None, 0);
+        batch.push_data(&[0xe8, 0x00]);
+        batch.push(
+            0x1001,
+            &[0xe8, 0x00],
+            &[0x1005],
+            Some(InstructionType::Call),
+            Some(0x1005),
+            0,
+        );
+
+        assert_eq!(batch.len(), 2);
+        let instructions: Vec<_> = batch.iter().collect();
+        assert_eq!(instructions[0].opcode, ".byte 0x06");
+        assert_eq!(instructions[0].addresses, &[] as &[u64]);
+        assert_eq!(instructions[1].opcode, ".byte 0xe8, 0x00");
+        assert_eq!(instructions[1].bytes, &[0xe8, 0x00]);
+        assert_eq!(instructions[1].addresses, &[0x1005]);
+        assert!(instructions[1].is_branch());
+        assert!(batch.get(2).is_none());
+
+        let instruction = instructions[1].to_instruction();
+        assert_eq!(instruction.offset, 0x1001);
+        assert_eq!(instruction.bytes(), &[0xe8, 0x00]);
+        assert_eq!(instruction.to_string(), instructions[1].to_string());
+    }
+}
@@ -6,10 +6,22 @@ use libc::{c_char, c_uint, c_ulong, uintptr_t};
 use std;
 use std::ffi::{CStr, CString};
 
-use helpers::{get_arch, get_mach, get_start_address, macro_bfd_big_endian, CURRENT_OPCODE};
+use arch::{bfd_get_arch_info, ArchInfo, Machine};
+use disassembly::Disassembly;
+use functions::{discover_functions, Function};
+use helpers::{
+    get_bfd_bits_per_address, get_bfd_elf_flags, get_bfd_filename, get_bfd_target_name,
+    get_first_section, get_start_address, macro_bfd_big_endian, CURRENT_MEMORY_ERROR,
+    CURRENT_OPCODE,
+};
+use instruction::Instruction;
 use opcodes::{disassembler, DisassembleInfo, DisassembleInfoRaw, DisassemblerFunction};
+use parallel::disassemble_parallel;
+use recursive::RecursiveDisassembly;
 use section::{Section, SectionRaw};
+use symbol::{Symbol, SymbolTable};
 use utils;
+use xref::XrefDatabase;
 use Error;
 
 extern "C" {
@@ -27,11 +39,13 @@ extern "C" {
 
     fn bfd_arch_list() -> *const uintptr_t;
 
-    fn bfd_scan_arch(string: *const c_char) -> *const c_uint;
+    fn bfd_target_list() -> *const *const c_char;
 
     fn bfd_get_arch(bfd: *const BfdRaw) -> c_uint;
 
     fn bfd_get_mach(bfd: *const BfdRaw) -> c_ulong;
+
+    fn bfd_printable_name(bfd: *const BfdRaw) -> *const c_char;
 }
 
 // Rust bfd types
@@ -42,7 +56,8 @@ pub(crate) enum BfdRaw {}
 #[derive(Clone, Copy)]
 pub struct Bfd {
     bfd: *const BfdRaw,
-    pub arch_mach: (u32, u64),
+    // Note: used to disassemble buffers, when there is no file
+    pub(crate) machine: Option<Machine>,
 }
 
 impl Bfd {
@@ -54,7 +69,15 @@ impl Bfd {
         unsafe { bfd_init() };
         Bfd {
             bfd: std::ptr::null(),
-            arch_mach: (0, 0),
+            machine: None,
+        }
+    }
+
+    // Note: used by worker threads, once bfd_init() was called
+    pub(crate) fn from_machine(machine: Machine) -> Bfd {
+        Bfd {
+            bfd: std::ptr::null(),
+            machine: Some(machine),
         }
     }
 
@@ -69,10 +92,7 @@ impl Bfd {
             return Err(bfd_convert_error());
         };
 
-        Ok(Bfd {
-            bfd,
-            arch_mach: (0, 0),
-        })
+        Ok(Bfd { bfd, machine: None })
     }
 
     pub fn check_format(&self, format: BfdFormat) -> Result<(), Error> {
@@ -98,28 +118,151 @@ impl Bfd {
         Ok(Section::from_raw(section)?)
     }
 
+    pub fn get_filename(&self) -> Result<String, Error> {
+        utils::check_null_pointer(self.bfd, "bfd pointer is null!")?;
+
+        let filename = unsafe { get_bfd_filename(self.bfd) };
+        utils::check_null_pointer(filename, "filename pointer is null!")?;
+        Ok(unsafe { CStr::from_ptr(filename) }.to_str()?.to_string())
+    }
+
+    pub fn get_target_name(&self) -> Result<String, Error> {
+        utils::check_null_pointer(self.bfd, "bfd pointer is null!")?;
+
+        let target = unsafe { get_bfd_target_name(self.bfd) };
+        utils::check_null_pointer(target, "target pointer is null!")?;
+        Ok(unsafe { CStr::from_ptr(target) }.to_str()?.to_string())
+    }
+
+    /// Return the architecture name, i.e. `i386:x86-64`
+    pub fn get_arch_name(&self) -> Result<String, Error> {
+        utils::check_null_pointer(self.bfd, "bfd pointer is null!")?;
+
+        let name = unsafe { bfd_printable_name(self.bfd) };
+        utils::check_null_pointer(name, "architecture name pointer is null!")?;
+        Ok(unsafe { CStr::from_ptr(name) }.to_str()?.to_string())
+    }
+
+    /// Return the ELF header flags, or 0 for other formats
+    pub fn get_elf_flags(&self) -> Result<u64, Error> {
+        utils::check_null_pointer(self.bfd, "bfd pointer is null!")?;
+
+        Ok(unsafe { get_bfd_elf_flags(self.bfd) })
+    }
+
+    pub fn get_bits_per_address(&self) -> Result<u32, Error> {
+        utils::check_null_pointer(self.bfd, "bfd pointer is null!")?;
+
+        Ok(unsafe { get_bfd_bits_per_address(self.bfd) })
+    }
+
+    pub fn get_sections(&self) -> Result<Vec<Section>, Error> {
+        utils::check_null_pointer(self.bfd, "bfd pointer is null!")?;
+
+        let mut sections = Vec::new();
+        let mut section = Section::from_raw(unsafe { get_first_section(self.bfd) }).ok();
+        while let Some(s) = section {
+            sections.push(s);
+            section = s.next();
+        }
+
+        Ok(sections)
+    }
+
+    pub fn disassemble_all(&self) -> Result<Disassembly, Error> {
+        Disassembly::new(*self)
+    }
+
+    pub fn cross_references(&self) -> Result<XrefDatabase, Error> {
+        XrefDatabase::new(*self)
+    }
+
+    pub fn discover_functions(&self) -> Result<Vec<Function>, Error> {
+        discover_functions(*self)
+    }
+
+    pub fn disassemble_parallel(
+        &self,
+        threads: usize,
+    ) -> Result<Vec<(Section, Instruction<'static>)>, Error> {
+        disassemble_parallel(*self, threads)
+    }
+
+    pub fn disassemble_recursive(&self) -> Result<RecursiveDisassembly, Error> {
+        RecursiveDisassembly::new(*self)
+    }
+
+    pub fn get_symbols(&self) -> Result<Vec<Symbol>, Error> {
+        SymbolTable::from_bfd(*self)?.symbols()
+    }
+
+    pub fn get_dynamic_symbols(&self) -> Result<Vec<Symbol>, Error> {
+        SymbolTable::dynamic_from_bfd(*self)?.symbols()
+    }
+
+    pub fn disassemble_symbol(&self, name: &str) -> Result<DisassembleInfo, Error> {
+        let symbols = self.get_symbols()?;
+        let symbol = match symbols.iter().find(|s| s.name == name && s.is_label()) {
+            Some(s) => s,
+            None => return Err(Error::SymbolError(name.to_string())),
+        };
+        let section = match symbol.section {
+            Some(s) => s,
+            None => return Err(Error::SymbolError(name.to_string())),
+        };
+
+        // The function ends at the end of the ELF symbol, the next symbol or the section
+        let start = symbol.address;
+        let section_end = section.get_vma()? + section.get_size()?;
+        let end = match symbol.size {
+            0 => symbols
+                .iter()
+                .filter(|s| s.is_label() && s.section == symbol.section && s.address > start)
+                .map(|s| s.address)
+                .min()
+                .unwrap_or(section_end),
+            size => start + size,
+        };
+
+        let mut info = DisassembleInfo::new()?;
+        info.configure(section, *self)?;
+        info.configure_disassembler(self.disassembler()?)?;
+        info.init()?;
+        info.set_range(start, end.min(section_end))?;
+
+        Ok(info)
+    }
+
+    /// Return the machine of the file, or the one configured with set_arch_mach()
+    pub fn get_machine(&self) -> Result<Machine, Error> {
+        if self.bfd.is_null() {
+            return self
+                .machine
+                .ok_or_else(|| Error::CommonError("architecture not set!".to_string()));
+        }
+
+        unsafe { Machine::from_raw(bfd_get_arch(self.bfd), bfd_get_mach(self.bfd)) }
+    }
+
     pub fn disassembler(&self) -> Result<Box<DisassemblerFunction>, Error> {
         utils::check_null_pointer(self.bfd, "bfd pointer is null!")?;
 
-        let arch = unsafe { bfd_get_arch(self.bfd) };
         let big_endian = match self.is_big_endian() {
             Ok(be) => be,
             Err(e) => return Err(e),
         };
-        let mach = unsafe { bfd_get_mach(self.bfd) };
-        self.raw_disassembler(arch, big_endian, mach)
+        self.raw_disassembler(self.get_machine()?, big_endian)
     }
 
     pub fn raw_disassembler(
         &self,
-        arch: c_uint,
+        machine: Machine,
         big_endian: bool,
-        mach: c_ulong,
     ) -> Result<Box<DisassemblerFunction>, Error> {
-        let disassemble_opt: Option<extern "C" fn(u64, *const DisassembleInfoRaw) -> u64> = unsafe { 
-            Some(disassembler(arch, big_endian, mach, self.bfd)) 
-        };
-        
+        let (arch, mach) = machine.raw();
+        let disassemble_opt: Option<extern "C" fn(u64, *const DisassembleInfoRaw) -> u64> =
+            unsafe { Some(disassembler(arch, big_endian, mach, self.bfd)) };
+
         if disassemble_opt.is_none() {
             return Err(Error::BfdError(
                 0,
@@ -131,10 +274,9 @@ impl Bfd {
         let disassemble_fn = disassemble_opt.unwrap();
 
         let disassemble_closure = move |p: c_ulong, di: &DisassembleInfo| -> c_ulong {
-            // Reset the buffer pointer
-            unsafe {
-                CURRENT_OPCODE = None;
-            }
+            // Reset the buffer pointer and the last memory error
+            CURRENT_OPCODE.with(|opcode| opcode.borrow_mut().clear());
+            CURRENT_MEMORY_ERROR.with(|error| error.set(None));
             disassemble_fn(p, di.raw())
         };
 
@@ -152,17 +294,48 @@ impl Bfd {
 
         Ok(unsafe { macro_bfd_big_endian(self.bfd) })
     }
-    pub fn set_arch_mach(&mut self, arch: &str) -> Result<(u32, u64), Error> {
-        let arch_cstring = CString::new(arch)?;
-        let arch_info = unsafe { bfd_scan_arch(arch_cstring.as_ptr()) };
-        if arch_info.is_null() {
-            return Err(Error::BfdError(0, "architecture not found!".to_string()));
-        };
-        self.arch_mach = unsafe { (get_arch(arch_info), get_mach(arch_info)) };
-        Ok(self.arch_mach)
+    pub fn set_arch_mach(&mut self, arch: &str) -> Result<Machine, Error> {
+        let machine = Machine::from_name(arch)?;
+        self.machine = Some(machine);
+        Ok(machine)
+    }
+
+    pub fn set_machine(&mut self, machine: Machine) {
+        self.machine = Some(machine);
+    }
+
+    /// Describe the machine returned by get_machine()
+    pub fn get_arch_info(&self) -> Result<ArchInfo, Error> {
+        match self.bfd.is_null() {
+            true => self.get_machine()?.arch_info(),
+            false => ArchInfo::from_raw(unsafe { bfd_get_arch_info(self.bfd) }),
+        }
     }
 }
 
+/// Return the names of the targets supported by libbfd, i.e. "elf64-x86-64"
+pub fn target_list() -> Vec<String> {
+    let list = unsafe { bfd_target_list() };
+    if list.is_null() {
+        return Vec::new();
+    }
+
+    let mut targets = Vec::new();
+    for index in 0.. {
+        let name = unsafe { *list.offset(index) };
+        if name.is_null() {
+            break;
+        }
+        let name = unsafe { CStr::from_ptr(name) };
+        targets.push(name.to_string_lossy().into_owned());
+    }
+
+    unsafe {
+        libc::free(list as *mut libc::c_void);
+    }
+    targets
+}
+
 pub fn arch_list() -> Vec<String> {
     let mut ret_vec = Vec::new();
     let mut index = 0;
@@ -193,7 +366,9 @@ pub fn arch_list() -> Vec<String> {
         }
     }
 
-    unsafe { libc::free(list as *mut libc::c_void); }
+    unsafe {
+        libc::free(list as *mut libc::c_void);
+    }
 
     ret_vec
 }
@@ -228,7 +403,7 @@ mod tests {
 
         let bfd = bfd::Bfd::empty();
         assert_eq!(bfd.bfd, std::ptr::null());
-        assert_eq!(bfd.arch_mach, (0, 0));
+        assert!(bfd.machine.is_none());
 
         match bfd.get_start_address() {
             Ok(_) => assert!(false),
@@ -244,6 +419,7 @@ mod tests {
     fn test_bfd_openr() {
         use bfd;
         use std;
+        use utils;
         use Error;
 
         let raw_binary_name = b"bin\0name".to_vec();
@@ -254,24 +430,31 @@ mod tests {
             Err(_) => assert!(false),
         };
 
-        match bfd::Bfd::openr("/bin/ls", "elf64-x86-64") {
-            Ok(_) => assert!(true),
-            Err(_) => assert!(false),
-        };
-
         match bfd::Bfd::openr("", "") {
             Ok(_) => assert!(false),
             Err(Error::BfdError(_, _)) => assert!(true),
             Err(_) => assert!(false),
         };
+
+        // Note: the target is not built without the x86 feature
+        if utils::has_arch("i386:x86-64") {
+            match bfd::Bfd::openr("/bin/ls", "elf64-x86-64") {
+                Ok(_) => assert!(true),
+                Err(_) => assert!(false),
+            };
+        }
     }
 
     #[test]
     fn test_bfd_get_section_bad() {
         use bfd;
         use std;
+        use utils;
         use Error;
 
+        if !utils::has_arch("i386:x86-64") {
+            return;
+        }
         let bfd = bfd::Bfd::openr("/bin/ls", "elf64-x86-64").unwrap();
         let raw_section_name = b".\0text".to_vec();
         let section_name = unsafe { std::str::from_utf8_unchecked(&raw_section_name) };
@@ -291,13 +474,20 @@ mod tests {
     #[test]
     fn test_bfd_get_section_good() {
         use bfd;
+        use libc::c_ulong;
+        use utils;
 
+        if !utils::has_arch("i386:x86-64") {
+            return;
+        }
         let bfd = bfd::Bfd::openr("/bin/ls", "elf64-x86-64").unwrap();
         bfd.check_format(bfd::BfdFormat::bfd_object).unwrap();
         match bfd.get_section_by_name(".text") {
             Ok(section) => {
                 assert!(!section.raw().is_null());
                 assert!(section.get_size().unwrap() > 0);
+                let contents = section.get_contents(bfd).unwrap();
+                assert_eq!(contents.len() as c_ulong, section.get_size().unwrap());
             }
             Err(_) => assert!(false),
         };
@@ -311,18 +501,44 @@ mod tests {
         use bfd;
 
         assert_eq!(bfd::arch_list()[0..2].len(), 2);
+        assert!(!bfd::target_list().is_empty());
+    }
+
+    #[test]
+    fn test_bfd_arch_info() {
+        use bfd;
+        use utils;
+
+        let mut bfd = bfd::Bfd::empty();
+        assert!(bfd.set_arch_mach("unknown").is_err());
+        assert!(bfd.get_machine().is_err());
+        if !utils::has_arch("i386:x86-64") {
+            return;
+        }
+        let machine = bfd.set_arch_mach("i386:x86-64").unwrap();
+        assert_eq!(bfd.get_arch_info().unwrap().machine().unwrap(), machine);
+
+        let bfd = match utils::open_test_binary() {
+            Some(b) => b,
+            None => return,
+        };
+        assert_eq!(bfd.get_arch_info().unwrap().bits_per_address, 64);
     }
 
     #[test]
     fn test_bfd_disassemble() {
         use bfd;
         use opcodes;
+        use utils;
 
+        if !utils::has_arch("i386:x86-64") {
+            return;
+        }
         let bfd = bfd::Bfd::openr("/bin/ls", "elf64-x86-64").unwrap();
         bfd.check_format(bfd::BfdFormat::bfd_object).unwrap();
         let section = bfd.get_section_by_name(".text").unwrap();
 
-        let info = match opcodes::DisassembleInfo::new() {
+        let mut info = match opcodes::DisassembleInfo::new() {
             Ok(i) => i,
             Err(_) => {
                 assert!(false);
@@ -335,4 +551,56 @@ mod tests {
             Err(_) => assert!(false),
         };
     }
+
+    #[test]
+    fn test_bfd_disassemble_bytes() {
+        use opcodes;
+        use utils;
+
+        let bfd = match utils::open_test_binary() {
+            Some(b) => b,
+            None => return,
+        };
+        let section = bfd.get_section_by_name(".text").unwrap();
+
+        let mut info = opcodes::DisassembleInfo::new().unwrap();
+        info.configure(section, bfd).unwrap();
+        info.configure_disassembler(bfd.disassembler().unwrap())
+            .unwrap();
+        info.init().unwrap();
+
+        let instruction = info.disassemble().unwrap().unwrap();
+        assert_eq!(instruction.bytes().len() as u64, instruction.length);
+    }
+
+    #[test]
+    fn test_bfd_disassemble_symbol() {
+        use utils;
+        use Error;
+
+        let bfd = match utils::open_test_binary() {
+            Some(b) => b,
+            None => return,
+        };
+        match bfd.disassemble_symbol("unknown") {
+            Err(Error::SymbolError(_)) => assert!(true),
+            _ => assert!(false),
+        };
+
+        let main = bfd
+            .get_symbols()
+            .unwrap()
+            .into_iter()
+            .find(|s| s.name == "main")
+            .unwrap();
+
+        let mut info = bfd.disassemble_symbol("main").unwrap();
+        let mut length = 0;
+        while let Some(instruction) = info.disassemble() {
+            let instruction = instruction.unwrap();
+            assert_eq!(instruction.offset, main.address + length);
+            length += instruction.length;
+        }
+        assert_eq!(length, main.size);
+    }
 }
@@ -0,0 +1,317 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - cfg.rs
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use instruction::{Instruction, InstructionType};
+use opcodes::DisassembleInfo;
+use Error;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeKind {
+    Fallthrough,
+    Conditional,
+    Unconditional,
+    // Note: call edges point to the called function, outside of the graph
+    Call,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Edge {
+    pub from: u64,
+    pub to: u64,
+    pub kind: EdgeKind,
+}
+
+/// Instructions that are always executed in sequence
+pub struct BasicBlock {
+    pub start: u64,
+    pub end: u64,
+    pub instructions: Vec<Instruction<'static>>,
+}
+
+/// Basic blocks of a function and the edges between them
+pub struct ControlFlowGraph {
+    pub entry: u64,
+    // Indexed by start address
+    pub blocks: BTreeMap<u64, BasicBlock>,
+    pub edges: Vec<Edge>,
+    // Blocks that return, jump outside of the function or use indirect branches
+    pub exits: Vec<u64>,
+}
+
+// How a block ends, once its delay slots are executed
+struct Terminator {
+    insn_type: InstructionType,
+    target: Option<u64>,
+}
+
+impl ControlFlowGraph {
+    /// Build the graph of the range configured in info, i.e. by Bfd::disassemble_symbol()
+    pub fn from_info(info: &mut DisassembleInfo) -> Result<ControlFlowGraph, Error> {
+        let start = info.current_vma();
+        let (_, end) = info.buffer_bounds();
+        ControlFlowGraph::new(info, start, end)
+    }
+
+    /// Build the graph of the function located at [start, end)
+    pub fn new(
+        info: &mut DisassembleInfo,
+        start: u64,
+        end: u64,
+    ) -> Result<ControlFlowGraph, Error> {
+        let mut instructions = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        // Indexed by the address of the last instruction of the block
+        let mut terminators = BTreeMap::new();
+
+        let mut pending = vec![start];
+        leaders.insert(start);
+        while let Some(mut pc) = pending.pop() {
+            while pc < end && !instructions.contains_key(&pc) {
+                info.set_range(pc, end)?;
+                let instruction = match info.disassemble() {
+                    Some(Ok(i)) => i,
+                    Some(Err(e)) => return Err(e),
+                    None => break,
+                };
+                pc += instruction.length;
+
+                let (insn_type, target) = match instruction.insn_type {
+                    Some(t) => (t, instruction.target),
+                    None => (InstructionType::NonBranch, None),
+                };
+                let mut last = instruction.offset;
+                let delay = instruction.branch_delay;
+                instructions.insert(instruction.offset, instruction);
+
+                // Delay slots belong to the block of the branch
+                for _ in 0..delay {
+                    if pc >= end {
+                        break;
+                    }
+                    info.set_range(pc, end)?;
+                    let slot = match info.disassemble() {
+                        Some(Ok(i)) => i,
+                        Some(Err(e)) => return Err(e),
+                        None => break,
+                    };
+                    last = slot.offset;
+                    pc += slot.length;
+                    instructions.insert(slot.offset, slot);
+                }
+
+                let local_target = target.filter(|t| *t >= start && *t < end);
+                match insn_type {
+                    InstructionType::Branch | InstructionType::ConditionalBranch => {
+                        if let Some(t) = local_target {
+                            leaders.insert(t);
+                            pending.push(t);
+                        }
+                        terminators.insert(last, Terminator { insn_type, target });
+                        if insn_type == InstructionType::Branch {
+                            break;
+                        }
+                        leaders.insert(pc);
+                    }
+                    InstructionType::ConditionalCall => {
+                        terminators.insert(last, Terminator { insn_type, target });
+                        leaders.insert(pc);
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        Ok(ControlFlowGraph::from_instructions(
+            start,
+            instructions,
+            &leaders,
+            &terminators,
+        ))
+    }
+
+    fn from_instructions(
+        entry: u64,
+        instructions: BTreeMap<u64, Instruction<'static>>,
+        leaders: &BTreeSet<u64>,
+        terminators: &BTreeMap<u64, Terminator>,
+    ) -> ControlFlowGraph {
+        let mut blocks: BTreeMap<u64, BasicBlock> = BTreeMap::new();
+        let mut edges = Vec::new();
+
+        // Split the instructions at leaders, after terminators, and at gaps
+        let mut current: Option<BasicBlock> = None;
+        for (address, instruction) in instructions {
+            let split = match current {
+                Some(ref block) => leaders.contains(&address) || block.end != address,
+                None => true,
+            };
+            if split {
+                if let Some(block) = current.take() {
+                    blocks.insert(block.start, block);
+                }
+            }
+
+            let terminated = terminators.contains_key(&address);
+            let block = current.get_or_insert_with(|| BasicBlock {
+                start: address,
+                end: address,
+                instructions: Vec::new(),
+            });
+            block.end = address + instruction.length;
+            block.instructions.push(instruction);
+
+            if terminated {
+                blocks.insert(block.start, current.take().unwrap());
+            }
+        }
+        if let Some(block) = current.take() {
+            blocks.insert(block.start, block);
+        }
+
+        let mut exits = Vec::new();
+        for block in blocks.values() {
+            for instruction in &block.instructions {
+                let call = instruction.insn_type == Some(InstructionType::Call)
+                    || instruction.insn_type == Some(InstructionType::ConditionalCall);
+                if let (true, Some(target)) = (call, instruction.target) {
+                    edges.push(Edge {
+                        from: block.start,
+                        to: target,
+                        kind: EdgeKind::Call,
+                    });
+                }
+            }
+
+            let last = match block.instructions.last() {
+                Some(i) => i.offset,
+                None => continue,
+            };
+            let local = |address: u64| blocks.contains_key(&address);
+            let (fallthrough, target) = match terminators.get(&last) {
+                Some(t) if t.insn_type == InstructionType::Branch => (false, t.target),
+                Some(t) if t.insn_type == InstructionType::ConditionalBranch => (true, t.target),
+                _ => (true, None),
+            };
+
+            let mut successors = 0;
+            if let Some(target) = target.filter(|t| local(*t)) {
+                let kind = match fallthrough {
+                    true => EdgeKind::Conditional,
+                    false => EdgeKind::Unconditional,
+                };
+                edges.push(Edge {
+                    from: block.start,
+                    to: target,
+                    kind,
+                });
+                successors += 1;
+            }
+            if fallthrough && local(block.end) {
+                edges.push(Edge {
+                    from: block.start,
+                    to: block.end,
+                    kind: EdgeKind::Fallthrough,
+                });
+                successors += 1;
+            }
+            if successors == 0 {
+                exits.push(block.start);
+            }
+        }
+
+        ControlFlowGraph {
+            entry,
+            blocks,
+            edges,
+            exits,
+        }
+    }
+
+    /// Return the edges leaving a block
+    pub fn successors(&self, block: u64) -> Vec<&Edge> {
+        self.edges
+            .iter()
+            .filter(|e| e.from == block && e.kind != EdgeKind::Call)
+            .collect()
+    }
+
+    /// Return the edges entering a block
+    pub fn predecessors(&self, block: u64) -> Vec<&Edge> {
+        self.edges
+            .iter()
+            .filter(|e| e.to == block && e.kind != EdgeKind::Call)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_cfg_x86() {
+        use cfg::{ControlFlowGraph, EdgeKind};
+        use opcodes::Endianness;
+        use utils;
+
+        // 0x1000: test eax,eax; je 0x100a
+        // 0x1004: call 0x1000; nop
+        // 0x100a: ret
+        let buffer = [
+            0x85, 0xc0, 0x74, 0x06, 0xe8, 0xf7, 0xff, 0xff, 0xff, 0x90, 0xc3,
+        ];
+        let mut info = match utils::test_buffer("i386:x86-64", &buffer, 0x1000, Endianness::Little)
+        {
+            Some(i) => i,
+            None => return,
+        };
+
+        let cfg = ControlFlowGraph::new(&mut info, 0x1000, 0x100b).unwrap();
+        let starts: Vec<_> = cfg.blocks.keys().cloned().collect();
+        assert_eq!(starts, vec![0x1000, 0x1004, 0x100a]);
+        assert_eq!(cfg.exits, vec![0x100a]);
+
+        let successors: Vec<_> = cfg.successors(0x1000).iter().map(|e| e.kind).collect();
+        assert_eq!(
+            successors,
+            vec![EdgeKind::Conditional, EdgeKind::Fallthrough]
+        );
+        assert_eq!(cfg.predecessors(0x100a).len(), 2);
+        assert!(cfg
+            .edges
+            .iter()
+            .any(|e| e.kind == EdgeKind::Call && e.from == 0x1004 && e.to == 0x1000));
+    }
+
+    #[test]
+    fn test_cfg_mips_delay_slot() {
+        use cfg::{ControlFlowGraph, EdgeKind};
+        use opcodes::Endianness;
+        use utils;
+
+        // 0x1000: beqz a0,0x100c; nop
+        // 0x1008: addiu v0,v0,1
+        // 0x100c: jr ra; nop
+        let buffer = [
+            0x10, 0x80, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x24, 0x42, 0x00, 0x01, 0x03, 0xe0,
+            0x00, 0x08, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let mut info = match utils::test_buffer("mips:isa32", &buffer, 0x1000, Endianness::Big) {
+            Some(i) => i,
+            None => return,
+        };
+
+        let cfg = ControlFlowGraph::new(&mut info, 0x1000, 0x1014).unwrap();
+        let starts: Vec<_> = cfg.blocks.keys().cloned().collect();
+        assert_eq!(starts, vec![0x1000, 0x1008, 0x100c]);
+        assert_eq!(cfg.blocks[&0x1000].end, 0x1008);
+        assert_eq!(cfg.blocks[&0x100c].end, 0x1014);
+        assert_eq!(cfg.exits, vec![0x100c]);
+
+        let successors: Vec<_> = cfg.successors(0x1000).iter().map(|e| e.kind).collect();
+        assert_eq!(
+            successors,
+            vec![EdgeKind::Conditional, EdgeKind::Fallthrough]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - disassembly.rs
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use bfd::Bfd;
+use instruction::Instruction;
+use opcodes::DisassembleInfo;
+use section::Section;
+use symbol::SymbolTable;
+use utils;
+use Error;
+
+/// Iterates over the instructions of all code sections, in address order
+pub struct Disassembly {
+    bfd: Bfd,
+    sections: VecDeque<Section>,
+    current: Option<(Section, DisassembleInfo)>,
+    recovery: bool,
+    // Sorted symbols shared by all sections, None if they can't be read
+    symbol_table: Option<Rc<SymbolTable>>,
+}
+
+impl Disassembly {
+    pub fn new(bfd: Bfd) -> Result<Disassembly, Error> {
+        utils::check_null_pointer(bfd.raw(), "bfd pointer is null!")?;
+
+        let mut sections = Vec::new();
+        for section in bfd.get_sections()? {
+            if section.is_code()? && section.get_size()? > 0 {
+                sections.push((section.get_vma()?, section));
+            }
+        }
+        sections.sort_by_key(|s| s.0);
+
+        Ok(Disassembly {
+            bfd,
+            sections: sections.into_iter().map(|s| s.1).collect(),
+            current: None,
+            recovery: false,
+            symbol_table: SymbolTable::sorted_from_bfd(bfd).ok().map(Rc::new),
+        })
+    }
+
+    /// Continue after undecodable bytes, see DisassembleInfo::set_recovery()
+    pub fn set_recovery(&mut self, enabled: bool) {
+        self.recovery = enabled;
+    }
+
+    fn configure(&self, section: Section) -> Result<DisassembleInfo, Error> {
+        let mut info = DisassembleInfo::new()?;
+        info.configure_with_symbols(section, self.bfd, self.symbol_table.clone())?;
+        info.configure_disassembler(self.bfd.disassembler()?)?;
+        info.init()?;
+        info.set_recovery(self.recovery);
+
+        let start = section.get_vma()?;
+        info.set_range(start, start + section.get_size()?)?;
+
+        Ok(info)
+    }
+}
+
+impl Iterator for Disassembly {
+    type Item = (Section, Instruction<'static>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // Move to the next code section
+            if self.current.is_none() {
+                let section = self.sections.pop_front()?;
+                match self.configure(section) {
+                    Ok(info) => self.current = Some((section, info)),
+                    Err(e) => return Some((section, Instruction::empty_with_error(Some(e)))),
+                }
+            }
+
+            let result = match self.current {
+                Some((_, ref mut info)) => info.disassemble(),
+                None => continue,
+            };
+
+            // Note: without recovery, an error stops the disassembly of the current section
+            match result {
+                Some(Ok(instruction)) => {
+                    let section = self.current.as_ref().map(|c| c.0)?;
+                    return Some((section, instruction));
+                }
+                Some(Err(e)) => {
+                    let section = self.current.take().map(|c| c.0)?;
+                    return Some((section, Instruction::empty_with_error(Some(e))));
+                }
+                None => self.current = None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_disassemble_all() {
+        use bfd;
+        use utils;
+
+        assert!(bfd::Bfd::empty().disassemble_all().is_err());
+
+        let bfd = match utils::open_test_binary() {
+            Some(b) => b,
+            None => return,
+        };
+
+        let mut previous = 0;
+        for (section, instruction) in bfd.disassemble_all().unwrap() {
+            if instruction.error.is_some() {
+                continue;
+            }
+
+            let start = section.get_vma().unwrap();
+            let end = start + section.get_size().unwrap();
+
+            assert!(instruction.offset >= start && instruction.offset < end);
+            assert!(instruction.offset > previous || previous == 0);
+            previous = instruction.offset;
+        }
+
+        // The last instruction is the ret of .fini
+        assert_eq!(previous, 0x11c4);
+    }
+}
@@ -0,0 +1,630 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - functions.rs
+
+use std::collections::BTreeMap;
+
+use bfd::Bfd;
+use helpers;
+use instruction::InstructionType;
+use section::Section;
+use symbol::{Symbol, BSF_FUNCTION};
+use utils;
+use Error;
+
+/// How a function was found, from the most to the least reliable
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FunctionSource {
+    Symbol,
+    DynamicSymbol,
+    EhFrame,
+    CallTarget,
+    Prologue,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub start: u64,
+    pub end: u64,
+    pub name: Option<String>,
+    pub source: FunctionSource,
+}
+
+// Instruction bytes that usually start a function
+struct Prologue {
+    bytes: &'static [u8],
+    mask: &'static [u8],
+}
+
+const X86_64_PROLOGUES: &[Prologue] = &[
+    // endbr64
+    Prologue {
+        bytes: &[0xf3, 0x0f, 0x1e, 0xfa],
+        mask: &[0xff, 0xff, 0xff, 0xff],
+    },
+    // push rbp; mov rbp,rsp
+    Prologue {
+        bytes: &[0x55, 0x48, 0x89, 0xe5],
+        mask: &[0xff, 0xff, 0xff, 0xff],
+    },
+];
+
+const I386_PROLOGUES: &[Prologue] = &[
+    // endbr32
+    Prologue {
+        bytes: &[0xf3, 0x0f, 0x1e, 0xfb],
+        mask: &[0xff, 0xff, 0xff, 0xff],
+    },
+    // push ebp; mov ebp,esp
+    Prologue {
+        bytes: &[0x55, 0x89, 0xe5],
+        mask: &[0xff, 0xff, 0xff],
+    },
+];
+
+// Note: little-endian encodings only
+const AARCH64_PROLOGUES: &[Prologue] = &[
+    // paciasp
+    Prologue {
+        bytes: &[0x3f, 0x23, 0x03, 0xd5],
+        mask: &[0xff, 0xff, 0xff, 0xff],
+    },
+    // stp x29, x30, [sp, #-N]!
+    Prologue {
+        bytes: &[0xfd, 0x7b, 0x80, 0xa9],
+        mask: &[0xff, 0x7f, 0xc0, 0xff],
+    },
+];
+
+const ARM_PROLOGUES: &[Prologue] = &[
+    // push {..., lr}
+    Prologue {
+        bytes: &[0x00, 0x40, 0x2d, 0xe9],
+        mask: &[0x00, 0x40, 0xff, 0xff],
+    },
+];
+
+// Return the prologues of an architecture and their alignment, or None if it is not supported
+// Note: ARM prologues only match ARM code, not Thumb code
+fn prologues(
+    arch_name: &str,
+    bits_per_word: u32,
+    big_endian: bool,
+) -> Option<(&'static [Prologue], u64)> {
+    // Note: x32 code uses the x86-64 instruction set
+    match (arch_name, bits_per_word, big_endian) {
+        ("i386", 64, false) => Some((X86_64_PROLOGUES, 16)),
+        ("i386", 32, false) => Some((I386_PROLOGUES, 16)),
+        ("aarch64", _, false) => Some((AARCH64_PROLOGUES, 4)),
+        ("arm", _, false) => Some((ARM_PROLOGUES, 4)),
+        _ => None,
+    }
+}
+
+/// Find functions using symbols, .eh_frame, call targets and prologues
+///
+/// Prologues are only searched in little-endian x86, AArch64 and ARM code.
+pub fn discover_functions(bfd: Bfd) -> Result<Vec<Function>, Error> {
+    utils::check_null_pointer(bfd.raw(), "bfd pointer is null!")?;
+
+    let mut code = Vec::new();
+    for section in bfd.get_sections()? {
+        if section.is_code()? && section.get_size()? > 0 {
+            let start = section.get_vma()?;
+            code.push((start, start + section.get_size()?, section));
+        }
+    }
+    code.sort_by_key(|s| s.0);
+    let in_code = |address: u64| code.iter().any(|s| s.0 <= address && address < s.1);
+
+    // Candidates indexed by start address, with an optional end and name
+    let mut candidates: BTreeMap<u64, (Option<u64>, Option<String>, FunctionSource)> =
+        BTreeMap::new();
+    let mut add = |start: u64, end: Option<u64>, name: Option<String>, source| {
+        if !in_code(start) {
+            return;
+        }
+        let entry = candidates.entry(start).or_insert((end, None, source));
+        entry.0 = entry.0.or(end);
+        entry.1 = entry.1.take().or(name);
+        entry.2 = entry.2.min(source);
+    };
+
+    let symbols = |source, symbols: Vec<Symbol>| {
+        symbols
+            .into_iter()
+            .filter(|s| s.is_label() && s.flags & BSF_FUNCTION != 0)
+            .map(move |s| {
+                let end = match s.size {
+                    0 => None,
+                    size => Some(s.address + size),
+                };
+                (s.address, end, Some(s.name), source)
+            })
+            .collect::<Vec<_>>()
+    };
+    for (start, end, name, source) in symbols(FunctionSource::Symbol, bfd.get_symbols()?) {
+        add(start, end, name, source);
+    }
+    // Note: the dynamic symbol table is optional
+    let dynamic = bfd.get_dynamic_symbols().unwrap_or_default();
+    for (start, end, name, source) in symbols(FunctionSource::DynamicSymbol, dynamic) {
+        add(start, end, name, source);
+    }
+
+    for (start, end) in eh_frame_ranges(bfd)? {
+        add(start, Some(end), None, FunctionSource::EhFrame);
+    }
+    // Note: the binary search table also covers FDEs that could not be parsed
+    for start in eh_frame_hdr_starts(bfd)? {
+        add(start, None, None, FunctionSource::EhFrame);
+    }
+
+    for (_, instruction) in bfd.disassemble_all()? {
+        if instruction.insn_type == Some(InstructionType::Call) {
+            if let Some(target) = instruction.target {
+                add(target, None, None, FunctionSource::CallTarget);
+            }
+        }
+    }
+
+    let arch_info = bfd.get_arch_info()?;
+    let big_endian = bfd.is_big_endian()?;
+    let supported = prologues(&arch_info.arch_name, arch_info.bits_per_word, big_endian);
+    if let Some((patterns, alignment)) = supported {
+        for &(start, end, _) in &code {
+            let data = read_vma(bfd, start, end - start)?;
+            let first = ((alignment - start % alignment) % alignment) as usize;
+            for offset in (first..data.len()).step_by(alignment as usize) {
+                if patterns
+                    .iter()
+                    .any(|p| matches_prologue(&data[offset..], p))
+                {
+                    add(start + offset as u64, None, None, FunctionSource::Prologue);
+                }
+            }
+        }
+    }
+
+    Ok(resolve_functions(candidates, &code))
+}
+
+fn matches_prologue(data: &[u8], prologue: &Prologue) -> bool {
+    data.len() >= prologue.bytes.len()
+        && prologue
+            .bytes
+            .iter()
+            .zip(prologue.mask)
+            .zip(data)
+            .all(|((b, m), d)| d & m == *b)
+}
+
+// Discard guesses located inside known functions, and compute missing ends
+fn resolve_functions(
+    candidates: BTreeMap<u64, (Option<u64>, Option<String>, FunctionSource)>,
+    code: &[(u64, u64, Section)],
+) -> Vec<Function> {
+    let mut functions: Vec<(Function, bool)> = Vec::new();
+    let mut known_end = 0;
+    for (start, (end, name, source)) in candidates {
+        if source >= FunctionSource::CallTarget && start < known_end {
+            continue;
+        }
+        if let Some(end) = end {
+            known_end = known_end.max(end);
+        }
+
+        let section_end = code
+            .iter()
+            .find(|s| s.0 <= start && start < s.1)
+            .map(|s| s.1)
+            .unwrap_or(start);
+        let function = Function {
+            start,
+            end: end.unwrap_or(section_end),
+            name,
+            source,
+        };
+        functions.push((function, end.is_some()));
+    }
+
+    // Functions without a known size end at the next one
+    for i in 1..functions.len() {
+        let next = functions[i].0.start;
+        let (ref mut previous, sized) = functions[i - 1];
+        if !sized && previous.end > next {
+            previous.end = next;
+        }
+    }
+    functions.into_iter().map(|f| f.0).collect()
+}
+
+fn read_vma(bfd: Bfd, vma: u64, length: u64) -> Result<Vec<u8>, Error> {
+    let mut data = vec![0; length as usize];
+    if !unsafe { helpers::read_bfd_vma(bfd.raw(), vma, data.as_mut_ptr(), length) } {
+        return Err(Error::MemoryError { vma });
+    }
+    Ok(data)
+}
+
+// Pointer encodings, from the LSB ABI
+const DW_EH_PE_OMIT: u8 = 0xff;
+const DW_EH_PE_ULEB128: u8 = 0x01;
+const DW_EH_PE_UDATA2: u8 = 0x02;
+const DW_EH_PE_UDATA4: u8 = 0x03;
+const DW_EH_PE_UDATA8: u8 = 0x04;
+const DW_EH_PE_SLEB128: u8 = 0x09;
+const DW_EH_PE_SDATA2: u8 = 0x0a;
+const DW_EH_PE_SDATA4: u8 = 0x0b;
+const DW_EH_PE_SDATA8: u8 = 0x0c;
+const DW_EH_PE_PCREL: u8 = 0x10;
+const DW_EH_PE_DATAREL: u8 = 0x30;
+
+// Reads DWARF values from a section mapped at vma
+struct EhReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    vma: u64,
+    big_endian: bool,
+    address_size: usize,
+}
+
+impl<'a> EhReader<'a> {
+    fn bytes(&mut self, count: usize) -> Option<u64> {
+        let bytes = self.data.get(self.position..self.position + count)?;
+        self.position += count;
+
+        let mut value = 0;
+        for i in 0..count {
+            let byte = match self.big_endian {
+                true => bytes[i],
+                false => bytes[count - 1 - i],
+            };
+            value = (value << 8) | u64::from(byte);
+        }
+        Some(value)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|v| v as u8)
+    }
+
+    fn uleb128(&mut self) -> Option<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= u64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+    }
+
+    fn sleb128(&mut self) -> Option<i64> {
+        let mut value: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= i64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Some(value);
+            }
+        }
+    }
+
+    // Read a pointer, then apply its relative encoding
+    fn pointer(&mut self, encoding: u8, data_base: u64) -> Option<u64> {
+        let address = self.vma + self.position as u64;
+        let value = match encoding & 0x0f {
+            0x00 => self.bytes(self.address_size)?,
+            DW_EH_PE_ULEB128 => self.uleb128()?,
+            DW_EH_PE_UDATA2 => self.bytes(2)?,
+            DW_EH_PE_UDATA4 => self.bytes(4)?,
+            DW_EH_PE_UDATA8 => self.bytes(8)?,
+            DW_EH_PE_SLEB128 => self.sleb128()? as u64,
+            DW_EH_PE_SDATA2 => self.bytes(2)? as i16 as u64,
+            DW_EH_PE_SDATA4 => self.bytes(4)? as i32 as u64,
+            DW_EH_PE_SDATA8 => self.bytes(8)?,
+            _ => return None,
+        };
+
+        match encoding & 0x70 {
+            0x00 => Some(value),
+            DW_EH_PE_PCREL => Some(address.wrapping_add(value)),
+            DW_EH_PE_DATAREL => Some(data_base.wrapping_add(value)),
+            _ => None,
+        }
+    }
+
+    // Read a NUL terminated string that ends before end
+    fn string(&mut self, end: usize) -> Option<&'a [u8]> {
+        let data = self.data.get(self.position..end)?;
+        let length = data.iter().position(|b| *b == 0)?;
+        self.position += length + 1;
+        Some(&data[..length])
+    }
+}
+
+// Read the [start, end) ranges of all FDEs
+fn eh_frame_ranges(bfd: Bfd) -> Result<Vec<(u64, u64)>, Error> {
+    let section = match bfd.get_section_by_name(".eh_frame") {
+        Ok(s) => s,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let vma = section.get_vma()?;
+    let data = read_vma(bfd, vma, section.get_size()?)?;
+
+    let mut reader = EhReader {
+        data: &data,
+        position: 0,
+        vma,
+        big_endian: bfd.is_big_endian()?,
+        address_size: bfd.get_bits_per_address()? as usize / 8,
+    };
+    Ok(parse_eh_frame(&mut reader))
+}
+
+fn parse_eh_frame(reader: &mut EhReader) -> Vec<(u64, u64)> {
+    let mut ranges = Vec::new();
+    // FDE pointer encodings, indexed by CIE offset
+    let mut encodings = BTreeMap::new();
+
+    while reader.position < reader.data.len() {
+        let start = reader.position;
+        let length = match reader.bytes(4) {
+            Some(0) | None => break,
+            Some(0xffff_ffff) => match reader.bytes(8) {
+                Some(l) => l,
+                None => break,
+            },
+            Some(l) => l,
+        };
+        let id_position = reader.position;
+        let next = match id_position.checked_add(length as usize) {
+            Some(n) if n <= reader.data.len() => n,
+            _ => break,
+        };
+
+        let id = match reader.bytes(4) {
+            Some(id) => id as usize,
+            None => break,
+        };
+        if id == 0 {
+            if let Some(encoding) = parse_cie(reader, next) {
+                encodings.insert(start, encoding);
+            }
+        } else if let Some(encoding) = id_position
+            .checked_sub(id)
+            .and_then(|cie| encodings.get(&cie))
+        {
+            let begin = reader.pointer(*encoding, 0);
+            let size = reader.pointer(*encoding & 0x0f, 0);
+            if let (Some(begin), Some(size)) = (begin, size) {
+                if size > 0 {
+                    ranges.push((begin, begin.wrapping_add(size)));
+                }
+            }
+        }
+
+        reader.position = next;
+    }
+
+    ranges
+}
+
+// Return the pointer encoding used by the FDEs of this CIE, which ends before end
+fn parse_cie(reader: &mut EhReader, end: usize) -> Option<u8> {
+    let version = reader.u8()?;
+    let augmentation = reader.string(end)?;
+    if augmentation.contains(&b'h') {
+        // Obsolete GNU eh_ptr
+        reader.bytes(reader.address_size)?;
+    }
+    if version >= 4 {
+        // Address and segment selector sizes
+        reader.bytes(2)?;
+    }
+    reader.uleb128()?;
+    reader.sleb128()?;
+    match version {
+        1 => reader.u8().map(u64::from)?,
+        _ => reader.uleb128()?,
+    };
+
+    let mut encoding = 0;
+    if augmentation.first() != Some(&b'z') {
+        return Some(encoding);
+    }
+    reader.uleb128()?;
+    for c in &augmentation[1..] {
+        match *c {
+            b'L' => {
+                reader.u8()?;
+            }
+            b'P' => {
+                let personality = reader.u8()?;
+                reader.pointer(personality & 0x0f, 0)?;
+            }
+            b'R' => encoding = reader.u8()?,
+            b'S' | b'B' => (),
+            _ => break,
+        }
+    }
+    Some(encoding)
+}
+
+/// Read the function starts indexed by .eh_frame_hdr
+pub fn eh_frame_hdr_starts(bfd: Bfd) -> Result<Vec<u64>, Error> {
+    let section = match bfd.get_section_by_name(".eh_frame_hdr") {
+        Ok(s) => s,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let vma = section.get_vma()?;
+    let data = read_vma(bfd, vma, section.get_size()?)?;
+
+    let mut reader = EhReader {
+        data: &data,
+        position: 0,
+        vma,
+        big_endian: bfd.is_big_endian()?,
+        address_size: bfd.get_bits_per_address()? as usize / 8,
+    };
+    Ok(parse_eh_frame_hdr(&mut reader).unwrap_or_default())
+}
+
+fn parse_eh_frame_hdr(reader: &mut EhReader) -> Option<Vec<u64>> {
+    let vma = reader.vma;
+    if reader.u8()? != 1 {
+        return None;
+    }
+    let eh_frame_pointer_encoding = reader.u8()?;
+    let count_encoding = reader.u8()?;
+    let table_encoding = reader.u8()?;
+    if count_encoding == DW_EH_PE_OMIT || table_encoding == DW_EH_PE_OMIT {
+        return None;
+    }
+
+    reader.pointer(eh_frame_pointer_encoding, vma)?;
+    let count = reader.pointer(count_encoding, vma)?;
+
+    let mut starts = Vec::new();
+    for _ in 0..count {
+        starts.push(reader.pointer(table_encoding, vma)?);
+        reader.pointer(table_encoding, vma)?;
+    }
+    Some(starts)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_parse_eh_frame() {
+        use functions::{parse_eh_frame, parse_eh_frame_hdr, EhReader};
+
+        let data = [
+            // CIE: zR augmentation, pcrel|sdata4 FDE pointers
+            0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x7a, 0x52, 0x00, 0x01, 0x78,
+            0x10, 0x01, 0x1b, 0x0c, 0x07, 0x08, 0x90, 0x01, 0x00, 0x00,
+            // FDE: 0x1000-0x1020
+            0x10, 0x00, 0x00, 0x00, 0x1c, 0x00, 0x00, 0x00, 0xe0, 0xef, 0xff, 0xff, 0x20, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Terminator
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        let mut reader = EhReader {
+            data: &data,
+            position: 0,
+            vma: 0x2000,
+            big_endian: false,
+            address_size: 8,
+        };
+        assert_eq!(parse_eh_frame(&mut reader), vec![(0x1000, 0x1020)]);
+
+        // A 64-bit length overflowing the position, then a CIE augmentation without NUL
+        let data = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ];
+        let mut reader = EhReader {
+            data: &data,
+            position: 0,
+            vma: 0x2000,
+            big_endian: false,
+            address_size: 8,
+        };
+        assert!(parse_eh_frame(&mut reader).is_empty());
+        let data = [
+            0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x7a, 0x52, 0x00,
+        ];
+        let mut reader = EhReader {
+            data: &data,
+            position: 0,
+            vma: 0x2000,
+            big_endian: false,
+            address_size: 8,
+        };
+        assert!(parse_eh_frame(&mut reader).is_empty());
+
+        // One entry, datarel|sdata4 relative to the header
+        let data = [
+            0x01, 0x1b, 0x03, 0x3b, 0x00, 0x10, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0xf0,
+            0xff, 0xff, 0x20, 0x10, 0x00, 0x00,
+        ];
+        let mut reader = EhReader {
+            data: &data,
+            position: 0,
+            vma: 0x3000,
+            big_endian: false,
+            address_size: 8,
+        };
+        assert_eq!(parse_eh_frame_hdr(&mut reader), Some(vec![0x2000]));
+    }
+
+    #[test]
+    fn test_discover_functions() {
+        use functions::FunctionSource;
+        use utils;
+
+        let bfd = match utils::open_test_binary() {
+            Some(b) => b,
+            None => return,
+        };
+
+        let functions = bfd.discover_functions().unwrap();
+        assert!(functions
+            .iter()
+            .any(|f| f.source == FunctionSource::EhFrame));
+        assert!(functions.windows(2).all(|w| w[0].start < w[1].start));
+        assert!(functions.iter().all(|f| f.start < f.end));
+
+        let square = functions.iter().find(|f| f.start == 0x1139).unwrap();
+        assert_eq!(square.end, 0x1148);
+        assert_eq!(square.name, Some("square".to_string()));
+        assert_eq!(square.source, FunctionSource::Symbol);
+    }
+
+    #[test]
+    fn test_eh_frame_test_binary() {
+        use functions::{eh_frame_hdr_starts, eh_frame_ranges};
+        use utils;
+
+        let bfd = match utils::open_test_binary() {
+            Some(b) => b,
+            None => return,
+        };
+
+        // FDEs reported by readelf --debug-dump=frames resources/tests/hello
+        assert_eq!(
+            eh_frame_ranges(bfd).unwrap(),
+            vec![
+                (0x1050, 0x1072),
+                (0x1020, 0x1040),
+                (0x1040, 0x1048),
+                (0x1139, 0x1148),
+                (0x1148, 0x1181),
+                (0x1181, 0x11ba),
+            ]
+        );
+        assert_eq!(
+            eh_frame_hdr_starts(bfd).unwrap(),
+            vec![0x1020, 0x1040, 0x1050, 0x1139, 0x1148, 0x1181]
+        );
+    }
+
+    #[test]
+    fn test_prologues() {
+        use functions::prologues;
+
+        assert!(prologues("i386", 64, false).is_some());
+        assert!(prologues("arm", 32, false).is_some());
+        assert!(prologues("arm", 32, true).is_none());
+        assert!(prologues("mips", 32, false).is_none());
+    }
+}
@@ -1,7 +1,13 @@
 // Guillaume Valadon <guillaume@valadon.net>
 // C based binutils and custom helpers
 
+#ifdef HAVE_CONFIG_H
 #include <config.h>
+#else
+// bfd.h requires config.h, which is not installed with the libraries
+#define PACKAGE "binutils-rs"
+#define PACKAGE_VERSION "0.1.1"
+#endif
 
 #include <stdarg.h>
 #include <stdbool.h>
@@ -12,7 +18,12 @@
 #include <dis-asm.h>
 #include <time.h>
 
+#ifdef HAVE_ELF_BFD_H
+#include <elf-bfd.h>
+#endif
+
 void buffer_to_rust(char *buffer);
+void memory_error_to_rust(int status, bfd_vma memaddr);
 
 
 // Silly macro that helps removing the unused warnings
@@ -32,44 +43,43 @@ Here we will implement copy_buffer_styled, which will just be a wrapper that ign
 int copy_buffer(void *useless, const char *format, ...);
 int copy_buffer_styled(void* user_data, enum disassembler_style style, const char* format, ...);
 
-int copy_buffer(void* useless, const char* format, ...) {
-    // Use the parameter to prevent optimization
-    (void) useless;  // mark as unused
-
+static int vcopy_buffer(const char* format, va_list ap) {
+    // Most fragments fit on the stack, so that malloc is only used for long ones
+    char buffer_asm[256];
 
-    va_list ap;
-    va_start(ap, format);
-    
-    int len = vsnprintf(NULL, 0, format, ap);
-    
-    va_end(ap);
+    va_list ap_copy;
+    va_copy(ap_copy, ap);
 
+    int len = vsnprintf(buffer_asm, sizeof(buffer_asm), format, ap);
     if (len < 0) {
         fprintf(stderr, "Warning: vsnprintf \n");
+    } else if ((size_t)len < sizeof(buffer_asm)) {
+        buffer_to_rust(buffer_asm);
+    } else {
+        char* long_buffer_asm = malloc(len + 1);
+        if (!long_buffer_asm) {
+            fprintf(stderr, "Warning: buffer_asm malloc failed \n");
+        } else {
+            vsnprintf(long_buffer_asm, len + 1, format, ap_copy);
+            buffer_to_rust(long_buffer_asm);
+            // buffer_to_rust copies the string, so we can safely free here.
+            free(long_buffer_asm);
+        }
     }
 
-    char * buffer_asm = malloc(len + 1);
+    va_end(ap_copy);
+    return len;
+}
 
-    if (!buffer_asm) {
-        fprintf(stderr, "Warning: buffer_asm malloc failed \n");
-    }
+int copy_buffer(void* useless, const char* format, ...) {
+    // Use the parameter to prevent optimization
+    (void) useless;  // mark as unused
 
+    va_list ap;
     va_start(ap, format);
-    // Use return value to check for truncation
-    int result = vsnprintf(buffer_asm, len + 1, format, ap);
-    
+    int result = vcopy_buffer(format, ap);
     va_end(ap);
 
-    if (result < 0 || result >= len + 1) {
-        fprintf(stderr, "Warning: Buffer overflow in copy_buffer\n");
-        buffer_asm[len] = '\0';
-    }
-
-    buffer_to_rust(buffer_asm);
-    
-    // buffer_to_rust uses to_string() which is now owned (cloned) so we can safely free here.
-    free(buffer_asm);
-
     return result;
 }
 
@@ -80,40 +90,18 @@ int copy_buffer_styled(void* user_data, enum disassembler_style style, const cha
 
     va_list ap;
     va_start(ap, format);
-    
-    int len = vsnprintf(NULL, 0, format, ap);
-    
-    va_end(ap);
-
-    if (len < 0) {
-        fprintf(stderr, "Warning: vsnprintf \n");
-    }
-
-    char * buffer_asm = malloc(len + 1);
-
-    if (!buffer_asm) {
-        fprintf(stderr, "Warning: buffer_asm malloc failed \n");
-    }
-
-    va_start(ap, format);
-    // Use return value to check for truncation
-    int result = vsnprintf(buffer_asm, len + 1, format, ap);
-    
+    int result = vcopy_buffer(format, ap);
     va_end(ap);
 
-    if (result < 0 || result >= len + 1) {
-        fprintf(stderr, "Warning: Buffer overflow in copy_buffer\n");
-        buffer_asm[len] = '\0';
-    }
-
-    buffer_to_rust(buffer_asm);
-    
-    // buffer_to_rust uses to_string() which is now owned (cloned) so we can safely free here.
-    free(buffer_asm);
-
     return result;
 }
 
+void memory_error(int status, bfd_vma memaddr, struct disassemble_info *info) {
+    /* Report the failing address to Rust instead of printing an error message */
+    UNUSED_VARIABLE(info);
+    memory_error_to_rust(status, memaddr);
+}
+
 void show_buffer(struct disassemble_info *info) {
     printf("len=%zu - vma=%lu\n", info->buffer_length, info->buffer_vma);
     printf("%p\n", info->buffer);
@@ -140,8 +128,12 @@ bfd_boolean configure_disassemble_info(struct disassemble_info *info, asection *
 
     /* Construct and configure the disassembler_info class using stdout */
     init_disassemble_info (info, stdout, (fprintf_ftype) copy_buffer, copy_buffer_styled);
+    info->memory_error_func = memory_error;
     info->arch = bfd_get_arch (bfdFile);
     info->mach = bfd_get_mach (bfdFile);
+    info->endian = bfd_big_endian (bfdFile) ? BFD_ENDIAN_BIG : BFD_ENDIAN_LITTLE;
+    info->endian_code = info->endian;
+    info->display_endian = info->endian;
     info->section = section;
 
     info->buffer_vma = section->vma;
@@ -153,10 +145,19 @@ bfd_boolean configure_disassemble_info(struct disassemble_info *info, asection *
     return bfd_malloc_and_get_section (bfdFile, section, &info->buffer);
 }
 
+void set_endianness(struct disassemble_info *info, bool big_endian, bool big_endian_code) {
+    /* Instructions may use a different byte order than data, i.e. on ARM BE8 */
+    info->endian = big_endian ? BFD_ENDIAN_BIG : BFD_ENDIAN_LITTLE;
+    info->endian_code = big_endian_code ? BFD_ENDIAN_BIG : BFD_ENDIAN_LITTLE;
+    info->display_endian = info->endian_code;
+}
+
 asection* configure_disassemble_info_buffer(
     struct disassemble_info *info,
     enum bfd_architecture arch,
     unsigned long mach,
+    bool big_endian,
+    bool big_endian_code,
     uint64_t vma,
     uint64_t length,
     uint8_t *buffer
@@ -167,9 +168,11 @@ asection* configure_disassemble_info_buffer(
     }
 
     init_disassemble_info(info, stdout, (fprintf_ftype) copy_buffer, copy_buffer_styled);
-    
+    info->memory_error_func = memory_error;
+
     info->arch = arch;
     info->mach = mach;
+    set_endianness(info, big_endian, big_endian_code);
     /* Configure the buffer that will be disassembled */
     info->buffer = buffer;
     info->buffer_length = length;
@@ -194,6 +197,54 @@ asection* configure_disassemble_info_buffer(
     return (asection*) section;
 }
 
+typedef int (*read_memory_func) (bfd_vma memaddr, bfd_byte *myaddr, unsigned int length, struct disassemble_info *dinfo);
+asection* configure_disassemble_info_memory(
+    struct disassemble_info *info,
+    enum bfd_architecture arch,
+    unsigned long mach,
+    bool big_endian,
+    bool big_endian_code,
+    uint64_t vma,
+    void *application_data,
+    read_memory_func read_function
+) {
+    if (info == NULL || application_data == NULL) {
+        fprintf(stderr, "Error: Null pointer passed to configure_disassemble_info_memory\n");
+        return NULL;
+    }
+
+    init_disassemble_info(info, stdout, (fprintf_ftype) copy_buffer, copy_buffer_styled);
+    info->memory_error_func = memory_error;
+
+    info->arch = arch;
+    info->mach = mach;
+    set_endianness(info, big_endian, big_endian_code);
+    /* Bytes are read lazily from the Rust memory source */
+    info->application_data = application_data;
+    info->read_memory_func = read_function;
+    info->buffer = NULL;
+    info->buffer_length = 0;
+    info->buffer_vma = vma;
+
+    asection *section = (asection*) calloc(1, sizeof(asection));
+    if (section) {
+        info->section = section;
+        info->section->vma = vma;
+    }
+
+    /* Set default flavor to intel for architectures that support it. */
+    info->disassembler_options = "intel";
+    return (asection*) section;
+}
+
+void* get_disassemble_info_application_data(struct disassemble_info *info) {
+  return info->application_data;
+}
+
+void set_disassemble_info_application_data(struct disassemble_info *info, void *application_data) {
+  info->application_data = application_data;
+}
+
 typedef void (*print_address_func) (bfd_vma addr, struct disassemble_info *dinfo);
 void set_print_address_func(struct disassemble_info *info, print_address_func print_function) {
     info->print_address_func = print_function;
@@ -207,6 +258,101 @@ unsigned long get_disassemble_info_section_vma(struct disassemble_info *info) {
   return info->section->vma;
 }
 
+unsigned long get_disassemble_info_buffer_vma(struct disassemble_info *info) {
+  return info->buffer_vma;
+}
+
+unsigned long get_disassemble_info_buffer_length(struct disassemble_info *info) {
+  return info->buffer_length;
+}
+
+const bfd_byte* get_disassemble_info_buffer(struct disassemble_info *info) {
+  return info->buffer;
+}
+
+unsigned int get_disassemble_info_octets_per_byte(struct disassemble_info *info) {
+  return info->octets_per_byte;
+}
+
+unsigned long get_disassemble_info_stop_vma(struct disassemble_info *info) {
+  return info->stop_vma;
+}
+
+void set_disassemble_info_stop_vma(struct disassemble_info *info, unsigned long vma) {
+  info->stop_vma = vma;
+}
+
+unsigned int get_disassemble_info_bytes_per_line(struct disassemble_info *info) {
+  return info->bytes_per_line;
+}
+
+unsigned int get_disassemble_info_bytes_per_chunk(struct disassemble_info *info) {
+  return info->bytes_per_chunk;
+}
+
+void set_disassemble_info_bytes_per_chunk(struct disassemble_info *info, unsigned int bytes) {
+  info->bytes_per_chunk = bytes;
+}
+
+bool is_disassemble_info_display_big_endian(struct disassemble_info *info) {
+  return info->display_endian == BFD_ENDIAN_BIG;
+}
+
+void reset_disassemble_info_insn_info(struct disassemble_info *info) {
+  /* Not all disassemblers fill these fields, so clear them before each instruction */
+  info->insn_info_valid = 0;
+  info->branch_delay_insns = 0;
+  info->data_size = 0;
+  info->insn_type = dis_noninsn;
+  info->target = 0;
+  info->target2 = 0;
+}
+
+bool is_disassemble_info_insn_info_valid(struct disassemble_info *info) {
+  return info->insn_info_valid != 0;
+}
+
+unsigned int get_disassemble_info_insn_type(struct disassemble_info *info) {
+  return info->insn_type;
+}
+
+unsigned long get_disassemble_info_target(struct disassemble_info *info) {
+  return info->target;
+}
+
+unsigned int get_disassemble_info_branch_delay_insns(struct disassemble_info *info) {
+  return info->branch_delay_insns;
+}
+
+const char* get_disassemble_info_arch_name(struct disassemble_info *info) {
+  return bfd_printable_arch_mach (info->arch, 0);
+}
+
+bool is_disassemble_info_x86(struct disassemble_info *info) {
+  return info->arch == bfd_arch_i386;
+}
+
+void set_disassemble_info_symbol_table(struct disassemble_info *info, asymbol **symbols, long count) {
+  /* Used by the ARM and AArch64 disassemblers to find mapping symbols, i.e. $a, $t, $x and $d */
+  info->symtab = symbols;
+  info->symtab_size = count;
+  info->symtab_pos = -1;
+}
+
+unsigned long get_disassemble_info_mach(struct disassemble_info *info) {
+  return info->mach;
+}
+
+void set_disassemble_info_mach(struct disassemble_info *info, unsigned long mach) {
+  /* Switch the ISA mode, i.e. to MIPS16 or microMIPS */
+  info->mach = mach;
+}
+
+int read_disassemble_info_memory(struct disassemble_info *info, bfd_vma vma, bfd_byte *buffer, unsigned int length) {
+  /* Read bytes like the disassembler does, from a buffer, a section or a Rust memory source */
+  return info->read_memory_func (vma, buffer, length, info);
+}
+
 void free_disassemble_info(struct disassemble_info *info, bool free_section) {
   /* Free the structure and allocated variable */
   if (info) {
@@ -227,6 +373,174 @@ unsigned int macro_bfd_big_endian(bfd *bfdFile) {
     return bfd_big_endian(bfdFile);
 }
 
+const char* get_bfd_filename(bfd *bfdFile) {
+    return bfd_get_filename (bfdFile);
+}
+
+const char* get_bfd_target_name(bfd *bfdFile) {
+    return bfd_get_target (bfdFile);
+}
+
+unsigned int get_bfd_bits_per_address(bfd *bfdFile) {
+    return bfd_arch_bits_per_address (bfdFile);
+}
+
+unsigned long get_bfd_elf_flags(bfd *bfdFile) {
+  /* ELF e_flags, i.e. the MIPS ASEs, 0 otherwise */
+#ifdef HAVE_ELF_BFD_H
+  if (bfd_get_flavour (bfdFile) == bfd_target_elf_flavour && elf_elfheader (bfdFile) != NULL)
+    return elf_elfheader (bfdFile)->e_flags;
+#else
+  UNUSED_VARIABLE(bfdFile);
+#endif
+  return 0;
+}
+
+asection* get_first_section(bfd *bfdFile) {
+    return bfdFile->sections;
+}
+
+bool read_bfd_vma(bfd *bfdFile, uint64_t vma, uint8_t *buffer, uint64_t length) {
+    /* Copy the content of the section that contains the requested range */
+    asection *section;
+    for (section = bfdFile->sections; section != NULL; section = section->next) {
+        if ((section->flags & SEC_HAS_CONTENTS) == 0)
+            continue;
+        if (vma < section->vma || vma + length > section->vma + section->size)
+            continue;
+        return bfd_get_section_contents(bfdFile, section, buffer, vma - section->vma, length);
+    }
+    return false;
+}
+
+
+asymbol** get_symbol_table(bfd *bfdFile, long *count) {
+    /* Return a malloc'ed array of symbols, and -1 in count on error */
+    *count = 0;
+    if ((bfd_get_file_flags (bfdFile) & HAS_SYMS) == 0)
+        return NULL;
+
+    long size = bfd_get_symtab_upper_bound (bfdFile);
+    if (size < 0) {
+        *count = -1;
+        return NULL;
+    }
+    if (size == 0)
+        return NULL;
+
+    asymbol **symbols = (asymbol **) malloc(size);
+    if (symbols == NULL) {
+        *count = -1;
+        return NULL;
+    }
+
+    *count = bfd_canonicalize_symtab (bfdFile, symbols);
+    if (*count <= 0) {
+        free(symbols);
+        return NULL;
+    }
+    return symbols;
+}
+
+
+asymbol** get_dynamic_symbol_table(bfd *bfdFile, long *count) {
+    /* Same as get_symbol_table(), using the dynamic symbols that survive stripping */
+    *count = 0;
+    if ((bfd_get_file_flags (bfdFile) & DYNAMIC) == 0)
+        return NULL;
+
+    long size = bfd_get_dynamic_symtab_upper_bound (bfdFile);
+    if (size < 0) {
+        *count = -1;
+        return NULL;
+    }
+    if (size == 0)
+        return NULL;
+
+    asymbol **symbols = (asymbol **) malloc(size);
+    if (symbols == NULL) {
+        *count = -1;
+        return NULL;
+    }
+
+    *count = bfd_canonicalize_dynamic_symtab (bfdFile, symbols);
+    if (*count <= 0) {
+        free(symbols);
+        return NULL;
+    }
+    return symbols;
+}
+
+bool find_nearest_line(bfd *bfdFile, asection *section, asymbol **symbols, bfd_vma vma,
+                       const char **filename, const char **function, unsigned int *line) {
+    /* Look up the debug information, i.e. DWARF line tables, of an address */
+    *filename = NULL;
+    *function = NULL;
+    *line = 0;
+    if (vma < section->vma || vma >= section->vma + section->size)
+        return false;
+    return bfd_find_nearest_line (bfdFile, section, symbols, vma - section->vma,
+                                  filename, function, line);
+}
+
+static int compare_symbol_values(const void *a, const void *b) {
+    bfd_vma value_a = bfd_asymbol_value (*(asymbol **) a);
+    bfd_vma value_b = bfd_asymbol_value (*(asymbol **) b);
+    if (value_a != value_b)
+        return value_a < value_b ? -1 : 1;
+    return 0;
+}
+
+void sort_symbol_table(asymbol **symbols, long count) {
+    /* libopcodes expects symbols sorted by address */
+    if (symbols != NULL && count > 1)
+        qsort(symbols, count, sizeof(asymbol *), compare_symbol_values);
+}
+
+/*** asymbol structure helpers ***/
+
+const char* get_symbol_name(asymbol *symbol) {
+  return bfd_asymbol_name (symbol);
+}
+
+unsigned long get_symbol_address(asymbol *symbol) {
+  return bfd_asymbol_value (symbol);
+}
+
+unsigned int get_symbol_flags(asymbol *symbol) {
+  return symbol->flags;
+}
+
+bool is_symbol_defined(asymbol *symbol) {
+  return !bfd_is_und_section (symbol->section) && !bfd_is_com_section (symbol->section);
+}
+
+asection* get_symbol_section(asymbol *symbol) {
+  return symbol->section;
+}
+
+unsigned char get_symbol_other(asymbol *symbol) {
+  /* ELF st_other, i.e. STO_MIPS16 or STO_MICROMIPS, 0 otherwise */
+#ifdef HAVE_ELF_BFD_H
+  if (symbol->the_bfd != NULL && bfd_asymbol_flavour (symbol) == bfd_target_elf_flavour)
+    return ((elf_symbol_type *) symbol)->internal_elf_sym.st_other;
+#else
+  UNUSED_VARIABLE(symbol);
+#endif
+  return 0;
+}
+
+unsigned long get_symbol_size(asymbol *symbol) {
+  /* Only ELF symbols have a size, 0 means that it is unknown */
+#ifdef HAVE_ELF_BFD_H
+  if (symbol->the_bfd != NULL && bfd_asymbol_flavour (symbol) == bfd_target_elf_flavour)
+    return ((elf_symbol_type *) symbol)->internal_elf_sym.st_size;
+#else
+  UNUSED_VARIABLE(symbol);
+#endif
+  return 0;
+}
+
 
 /*** bfd_arch_info structure helpers ***/
 
@@ -238,9 +552,53 @@ unsigned long get_mach(struct bfd_arch_info *arch_info) {
   return arch_info->mach;
 }
 
+const char* get_arch_info_arch_name(struct bfd_arch_info *arch_info) {
+  return arch_info->arch_name;
+}
+
+const char* get_arch_info_printable_name(struct bfd_arch_info *arch_info) {
+  return arch_info->printable_name;
+}
+
+unsigned int get_arch_info_bits_per_word(struct bfd_arch_info *arch_info) {
+  return arch_info->bits_per_word;
+}
+
+unsigned int get_arch_info_bits_per_address(struct bfd_arch_info *arch_info) {
+  return arch_info->bits_per_address;
+}
+
+unsigned int get_arch_info_bits_per_byte(struct bfd_arch_info *arch_info) {
+  return arch_info->bits_per_byte;
+}
+
+unsigned int get_arch_info_section_align_power(struct bfd_arch_info *arch_info) {
+  return arch_info->section_align_power;
+}
+
+bool is_arch_info_default(struct bfd_arch_info *arch_info) {
+  return arch_info->the_default;
+}
+
 
 /*** section structure helpers ***/
 
 unsigned long get_section_size(asection *section) {
     return section->size;
 }
+
+unsigned long get_section_vma(asection *section) {
+    return section->vma;
+}
+
+unsigned int get_section_flags(asection *section) {
+    return section->flags;
+}
+
+const char* get_section_name(asection *section) {
+    return section->name;
+}
+
+asection* get_next_section(asection *section) {
+    return section->next;
+}
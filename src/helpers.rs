@@ -3,13 +3,15 @@
 
 #![doc(hidden)]
 
+use std::cell::{Cell, RefCell};
 use std::ffi::CStr;
 
-use libc::{c_char, c_uint, c_ulong, uintptr_t};
+use libc::{c_char, c_int, c_long, c_uint, c_ulong, c_void, uintptr_t};
 
 use bfd::BfdRaw;
 use opcodes::DisassembleInfoRaw;
 use section::SectionRaw;
+use symbol::SymbolRaw;
 
 extern "C" {
     // libbfd helpers
@@ -17,10 +19,36 @@ extern "C" {
 
     pub(crate) fn get_start_address(bfd: *const BfdRaw) -> c_ulong;
 
+    pub(crate) fn get_bfd_filename(bfd: *const BfdRaw) -> *const c_char;
+
+    pub(crate) fn get_bfd_target_name(bfd: *const BfdRaw) -> *const c_char;
+
+    pub(crate) fn get_bfd_bits_per_address(bfd: *const BfdRaw) -> c_uint;
+
+    pub(crate) fn get_bfd_elf_flags(bfd: *const BfdRaw) -> c_ulong;
+
+    pub(crate) fn get_first_section(bfd: *const BfdRaw) -> *const SectionRaw;
+
+    pub(crate) fn read_bfd_vma(bfd: *const BfdRaw, vma: u64, buffer: *mut u8, length: u64) -> bool;
+
     pub(crate) fn get_arch(arch_info: *const c_uint) -> u32;
 
     pub(crate) fn get_mach(arch_info: *const c_uint) -> u64;
 
+    pub(crate) fn get_arch_info_arch_name(arch_info: *const c_uint) -> *const c_char;
+
+    pub(crate) fn get_arch_info_printable_name(arch_info: *const c_uint) -> *const c_char;
+
+    pub(crate) fn get_arch_info_bits_per_word(arch_info: *const c_uint) -> c_uint;
+
+    pub(crate) fn get_arch_info_bits_per_address(arch_info: *const c_uint) -> c_uint;
+
+    pub(crate) fn get_arch_info_bits_per_byte(arch_info: *const c_uint) -> c_uint;
+
+    pub(crate) fn get_arch_info_section_align_power(arch_info: *const c_uint) -> c_uint;
+
+    pub(crate) fn is_arch_info_default(arch_info: *const c_uint) -> bool;
+
     // libopcodes helpers
     pub(crate) fn new_disassemble_info() -> *const DisassembleInfoRaw;
 
@@ -34,11 +62,33 @@ extern "C" {
         info: *const DisassembleInfoRaw,
         arch: c_uint,
         mach: c_ulong,
+        big_endian: bool,
+        big_endian_code: bool,
         vma: u64,
         length: u64,
         buffer: *const u8,
     ) -> *const SectionRaw;
 
+    pub(crate) fn configure_disassemble_info_memory(
+        info: *const DisassembleInfoRaw,
+        arch: c_uint,
+        mach: c_ulong,
+        big_endian: bool,
+        big_endian_code: bool,
+        vma: u64,
+        application_data: *mut c_void,
+        read_function: extern "C" fn(c_ulong, *mut u8, c_uint, *const DisassembleInfoRaw) -> c_int,
+    ) -> *const SectionRaw;
+
+    pub(crate) fn get_disassemble_info_application_data(
+        info: *const DisassembleInfoRaw,
+    ) -> *mut c_void;
+
+    pub(crate) fn set_disassemble_info_application_data(
+        info: *const DisassembleInfoRaw,
+        application_data: *mut c_void,
+    );
+
     pub(crate) fn set_print_address_func(
         info: *const DisassembleInfoRaw,
         print_function: extern "C" fn(c_ulong, *const uintptr_t),
@@ -52,26 +102,103 @@ extern "C" {
 
     pub(crate) fn get_disassemble_info_section_vma(info: *const DisassembleInfoRaw) -> c_ulong;
 
+    pub(crate) fn get_disassemble_info_buffer_vma(info: *const DisassembleInfoRaw) -> c_ulong;
+
+    pub(crate) fn get_disassemble_info_buffer_length(info: *const DisassembleInfoRaw) -> c_ulong;
+
+    pub(crate) fn get_disassemble_info_buffer(info: *const DisassembleInfoRaw) -> *const u8;
+
+    pub(crate) fn get_disassemble_info_octets_per_byte(info: *const DisassembleInfoRaw) -> c_uint;
+
+    pub(crate) fn get_disassemble_info_stop_vma(info: *const DisassembleInfoRaw) -> c_ulong;
+
+    pub(crate) fn set_disassemble_info_stop_vma(info: *const DisassembleInfoRaw, vma: c_ulong);
+
+    pub(crate) fn get_disassemble_info_bytes_per_line(info: *const DisassembleInfoRaw) -> c_uint;
+
+    pub(crate) fn get_disassemble_info_bytes_per_chunk(info: *const DisassembleInfoRaw) -> c_uint;
+
+    pub(crate) fn set_disassemble_info_bytes_per_chunk(
+        info: *const DisassembleInfoRaw,
+        bytes: c_uint,
+    );
+
+    pub(crate) fn is_disassemble_info_display_big_endian(info: *const DisassembleInfoRaw) -> bool;
+
+    pub(crate) fn reset_disassemble_info_insn_info(info: *const DisassembleInfoRaw);
+
+    pub(crate) fn is_disassemble_info_insn_info_valid(info: *const DisassembleInfoRaw) -> bool;
+
+    pub(crate) fn get_disassemble_info_insn_type(info: *const DisassembleInfoRaw) -> c_uint;
+
+    pub(crate) fn get_disassemble_info_target(info: *const DisassembleInfoRaw) -> c_ulong;
+
+    pub(crate) fn get_disassemble_info_branch_delay_insns(
+        info: *const DisassembleInfoRaw,
+    ) -> c_uint;
+
+    pub(crate) fn get_disassemble_info_arch_name(info: *const DisassembleInfoRaw) -> *const c_char;
+
+    pub(crate) fn is_disassemble_info_x86(info: *const DisassembleInfoRaw) -> bool;
+
+    pub(crate) fn set_disassemble_info_symbol_table(
+        info: *const DisassembleInfoRaw,
+        symbols: *mut *const SymbolRaw,
+        count: c_long,
+    );
+
+    pub(crate) fn get_disassemble_info_mach(info: *const DisassembleInfoRaw) -> c_ulong;
+
+    pub(crate) fn set_disassemble_info_mach(info: *const DisassembleInfoRaw, mach: c_ulong);
+
+    pub(crate) fn read_disassemble_info_memory(
+        info: *const DisassembleInfoRaw,
+        vma: c_ulong,
+        buffer: *mut u8,
+        length: c_uint,
+    ) -> c_int;
+
     // Custom helpers
     #[allow(dead_code)]
     pub(crate) fn show_buffer(info: *const DisassembleInfoRaw);
 }
 
-pub(crate) static mut CURRENT_OPCODE: Option<String> = None;
+thread_local! {
+    // Note: - each thread disassembles with its own buffer
+    //       - the buffer is reused across instructions to avoid reallocations
+    pub(crate) static CURRENT_OPCODE: RefCell<String> = const { RefCell::new(String::new()) };
+
+    pub(crate) static CURRENT_MEMORY_ERROR: Cell<Option<u64>> = const { Cell::new(None) };
+
+    // Set when libopcodes prints a (bad) fragment for an undecodable encoding
+    pub(crate) static CURRENT_BAD_INSTRUCTION: Cell<bool> = const { Cell::new(false) };
+}
 
 /// # Safety
 ///
-/// This function is used to copy the disassembly buffer to a static variable
+/// This function is used to copy the disassembly buffer to a thread local variable
 #[no_mangle]
 pub unsafe extern "C" fn buffer_to_rust(buffer: *const c_char) {
     let buffer_cstr = CStr::from_ptr(buffer);
-    let current_string = match CURRENT_OPCODE {
-        Some(ref o) => o,
-        None => "",
-    };
-    let new_string = match buffer_cstr.to_str() {
-        Ok(s) => s.to_string(),
-        Err(e) => format!("buffer_to_rust() - {}", e),
-    };
-    CURRENT_OPCODE = Some(format!("{}{}", current_string, new_string));
+    CURRENT_OPCODE.with(|opcode| {
+        let mut opcode = opcode.borrow_mut();
+        match buffer_cstr.to_str() {
+            Ok(s) => {
+                // Note: libopcodes has no status for undecodable encodings, it prints this instead
+                if s.trim() == "(bad)" {
+                    CURRENT_BAD_INSTRUCTION.with(|bad| bad.set(true));
+                }
+                opcode.push_str(s)
+            }
+            Err(e) => opcode.push_str(&format!("buffer_to_rust() - {}", e)),
+        }
+    });
+}
+
+/// # Safety
+///
+/// This function is used to record the address that libopcodes failed to read
+#[no_mangle]
+pub unsafe extern "C" fn memory_error_to_rust(_status: c_int, vma: c_ulong) {
+    CURRENT_MEMORY_ERROR.with(|error| error.set(Some(vma)));
 }
@@ -5,9 +5,75 @@ use std::fmt;
 
 use bfd::Bfd;
 use helpers;
-use opcodes::DisassembleInfo;
+use opcodes::{DisassembleInfo, Endianness};
 use Error;
 
+/// Kind of instruction, as reported by libopcodes in info->insn_type
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InstructionType {
+    NonInstruction,
+    NonBranch,
+    Branch,
+    ConditionalBranch,
+    Call,
+    ConditionalCall,
+    DataReference,
+    DataReference2,
+}
+
+impl InstructionType {
+    pub(crate) fn from_raw(insn_type: u32) -> Option<InstructionType> {
+        match insn_type {
+            0 => Some(InstructionType::NonInstruction),
+            1 => Some(InstructionType::NonBranch),
+            2 => Some(InstructionType::Branch),
+            3 => Some(InstructionType::ConditionalBranch),
+            4 => Some(InstructionType::Call),
+            5 => Some(InstructionType::ConditionalCall),
+            6 => Some(InstructionType::DataReference),
+            7 => Some(InstructionType::DataReference2),
+            _ => None,
+        }
+    }
+
+    // libopcodes does not classify x86 instructions, so use their mnemonics instead
+    pub(crate) fn from_x86_opcode(opcode: &str) -> InstructionType {
+        let prefixes = [
+            "bnd", "notrack", "rep", "repz", "repnz", "data16", "addr32", "ds", "cs",
+        ];
+        let mnemonic = opcode
+            .split_whitespace()
+            .find(|w| !prefixes.contains(w))
+            .unwrap_or("");
+
+        match mnemonic {
+            "jmp" | "ljmp" | "ret" | "retf" | "lret" | "iret" | "iretd" | "iretq" => {
+                InstructionType::Branch
+            }
+            "call" | "lcall" => InstructionType::Call,
+            "loop" | "loope" | "loopne" => InstructionType::ConditionalBranch,
+            m if m.starts_with('j') => InstructionType::ConditionalBranch,
+            _ => InstructionType::NonBranch,
+        }
+    }
+
+    /// Return true if execution never continues to the next instruction
+    pub fn ends_flow(self) -> bool {
+        self == InstructionType::Branch
+    }
+
+    /// Return true if the instruction may transfer execution to its target
+    pub fn is_branch(self) -> bool {
+        matches!(
+            self,
+            InstructionType::Branch
+                | InstructionType::ConditionalBranch
+                | InstructionType::Call
+                | InstructionType::ConditionalCall
+        )
+    }
+}
+
 #[allow(dead_code)]
 pub struct Instruction<'a> {
     pub length: u64,
@@ -15,6 +81,13 @@ pub struct Instruction<'a> {
     pub opcode: String,
     info: Option<&'a mut DisassembleInfo>,
     pub error: Option<Error>,
+    bytes: Vec<u8>,
+    addresses: Vec<u64>,
+    // Note: None if the disassembler does not provide this information
+    pub insn_type: Option<InstructionType>,
+    pub target: Option<u64>,
+    // Number of instructions executed after a branch, i.e. on MIPS and SPARC
+    pub branch_delay: u32,
 }
 
 impl<'a> fmt::Display for Instruction<'a> {
@@ -24,14 +97,17 @@ impl<'a> fmt::Display for Instruction<'a> {
 }
 
 pub(crate) fn get_opcode() -> Result<String, Error> {
-    unsafe {
-        let ret = match helpers::CURRENT_OPCODE {
-            None => Err(Error::DisassembleInfoError("Empty opcode!".to_string())),
-            Some(ref opcode) => Ok(opcode.clone()),
-        };
-        helpers::CURRENT_OPCODE = None;
-        ret
-    }
+    helpers::CURRENT_OPCODE.with(|opcode| {
+        let mut opcode = opcode.borrow_mut();
+        if opcode.is_empty() {
+            return Err(Error::DisassembleInfoError("Empty opcode!".to_string()));
+        }
+
+        // Copy the text, and keep the buffer capacity for the next instruction
+        let ret = opcode.as_str().to_string();
+        opcode.clear();
+        Ok(ret)
+    })
 }
 
 pub fn get_instruction<'a>(offset: u64, length: u64) -> Result<Instruction<'a>, Error> {
@@ -41,6 +117,11 @@ pub fn get_instruction<'a>(offset: u64, length: u64) -> Result<Instruction<'a>,
         opcode: get_opcode()?,
         info: None,
         error: None,
+        bytes: Vec::new(),
+        addresses: Vec::new(),
+        insn_type: None,
+        target: None,
+        branch_delay: 0,
     })
 }
 
@@ -52,6 +133,11 @@ impl<'a> Instruction<'a> {
             opcode: String::new(),
             info: None,
             error,
+            bytes: Vec::new(),
+            addresses: Vec::new(),
+            insn_type: None,
+            target: None,
+            branch_delay: 0,
         }
     }
     pub fn from_buffer(
@@ -59,18 +145,51 @@ impl<'a> Instruction<'a> {
         bfd: Bfd,
         buffer: &[u8],
         offset: u64,
+        endian: Endianness,
     ) -> Instruction<'a> {
-        match info.init_buffer(buffer, bfd, offset) {
+        match info.init_buffer(buffer, bfd, offset, endian) {
             Ok(_) => (),
             Err(e) => return Instruction::empty_with_error(Some(e)),
         };
 
+        Instruction::from_info(info)
+    }
+
+    /// Return the bytes that encode the instruction
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub(crate) fn set_bytes(&mut self, bytes: Vec<u8>) {
+        self.bytes = bytes;
+    }
+
+    /// Return the addresses printed in the operands, such as branch targets or RIP-relative data
+    pub fn addresses(&self) -> &[u64] {
+        &self.addresses
+    }
+
+    pub(crate) fn set_addresses(&mut self, addresses: Vec<u64>) {
+        self.addresses = addresses;
+    }
+
+    /// Return true if the instruction is a jump, a call or a return
+    pub fn is_branch(&self) -> bool {
+        self.insn_type.map(|t| t.is_branch()).unwrap_or(false)
+    }
+
+    pub(crate) fn from_info(info: &'a mut DisassembleInfo) -> Instruction<'a> {
         Instruction {
             offset: 0,
             length: 0,
             opcode: String::new(),
             info: Some(info),
             error: None,
+            bytes: Vec::new(),
+            addresses: Vec::new(),
+            insn_type: None,
+            target: None,
+            branch_delay: 0,
         }
     }
 }
@@ -80,12 +199,14 @@ impl<'a> Iterator for Instruction<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         // Temporarily remove info from the structure
+        // Note: a pending error is reported once, then the iteration stops
         let info = match self.info.take() {
             Some(i) => i,
             None => {
-                return Some(Instruction::empty_with_error(Some(
-                    Error::DisassembleInfoError("empty".to_string()),
-                )))
+                return self
+                    .error
+                    .take()
+                    .map(|e| Instruction::empty_with_error(Some(e)))
             }
         };
 
@@ -96,7 +217,7 @@ impl<'a> Iterator for Instruction<'a> {
                     self.info = Some(info);
                     Some(i)
                 }
-                Err(_) => None,
+                Err(e) => Some(Instruction::empty_with_error(Some(e))),
             },
             None => None,
         }
@@ -115,20 +236,82 @@ mod tests {
 
     #[test]
     fn test_iterator() {
-        use bfd;
         use instruction;
         use opcodes;
+        use utils;
 
-        let mut bfd = bfd::Bfd::empty();
-        let _ = bfd.set_arch_mach("i386:x86-64");
+        let bfd = match utils::test_bfd("i386:x86-64") {
+            Some(b) => b,
+            None => return,
+        };
 
         let mut info = opcodes::DisassembleInfo::new().unwrap();
 
         let buffer = vec![0x90];
-        let mut instruction = instruction::Instruction::from_buffer(&mut info, bfd, &buffer, 0);
+        let mut instruction = instruction::Instruction::from_buffer(
+            &mut info,
+            bfd,
+            &buffer,
+            0,
+            opcodes::Endianness::Little,
+        );
         match instruction.next() {
             Some(i) => assert_eq!(i.opcode, "nop"),
             None => assert!(false),
         };
     }
+
+    #[test]
+    fn test_bytes() {
+        use instruction;
+        use opcodes;
+        use utils;
+
+        let bfd = match utils::test_bfd("i386:x86-64") {
+            Some(b) => b,
+            None => return,
+        };
+
+        let mut info = opcodes::DisassembleInfo::new().unwrap();
+
+        let buffer = vec![0x90, 0xe8, 0x00, 0x00, 0x00, 0x00];
+        let instructions: Vec<_> = instruction::Instruction::from_buffer(
+            &mut info,
+            bfd,
+            &buffer,
+            0,
+            opcodes::Endianness::Little,
+        )
+        .collect();
+        assert_eq!(instructions[0].bytes(), &[0x90]);
+        assert_eq!(instructions[1].bytes(), &[0xe8, 0x00, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_instruction_type() {
+        use instruction::InstructionType;
+        use opcodes::Endianness;
+        use utils;
+
+        // call 0x1007; jmp QWORD PTR [rip+0x0]; ret
+        let buffer = [
+            0xe8, 0x02, 0x00, 0x00, 0x00, 0xff, 0x25, 0x00, 0x00, 0x00, 0x00, 0xc3,
+        ];
+        let mut info = match utils::test_buffer("i386:x86-64", &buffer, 0x1000, Endianness::Little)
+        {
+            Some(i) => i,
+            None => return,
+        };
+
+        let call = info.disassemble().unwrap().unwrap();
+        assert_eq!(call.insn_type, Some(InstructionType::Call));
+        assert_eq!(call.target, Some(0x1007));
+
+        let jmp = info.disassemble().unwrap().unwrap();
+        assert_eq!(jmp.insn_type, Some(InstructionType::Branch));
+        assert_eq!(jmp.target, None);
+
+        let ret = info.disassemble().unwrap().unwrap();
+        assert!(ret.insn_type.unwrap().ends_flow());
+    }
 }
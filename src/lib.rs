@@ -1,13 +1,27 @@
 // Guillaume Valadon <guillaume@valadon.net>
 // binutils - lib.rs
 
+pub mod arch;
+pub mod batch;
 pub mod bfd;
+pub mod cfg;
+pub mod disassembly;
+pub mod functions;
 pub mod helpers;
 pub mod instruction;
+pub mod listing;
 pub mod mach;
+pub mod memory;
+pub mod mips;
 pub mod opcodes;
+pub mod parallel;
+pub mod recursive;
 pub mod section;
+pub mod source;
+pub mod symbol;
+pub mod symbolizer;
 pub mod utils;
+pub mod xref;
 
 extern crate libc;
 
@@ -19,10 +33,15 @@ pub enum Error {
     BfdError(u32, String),
     DisassembleInfoError(String),
     SectionError(String),
+    SymbolError(String),
     CommonError(String),
     NulError(String),
     Utf8Error(std::str::Utf8Error),
+    IoError(std::io::Error),
     NullPointerError(String),
+    MemoryError { vma: u64 },
+    TruncatedInstruction { vma: u64 },
+    InvalidInstruction { vma: u64 },
 }
 
 impl fmt::Display for Error {
@@ -31,10 +50,17 @@ impl fmt::Display for Error {
             Error::BfdError(tag, ref msg) => write!(f, "{} ({})", msg, tag),
             Error::DisassembleInfoError(ref msg) => write!(f, "{}", msg),
             Error::SectionError(ref section) => write!(f, "Can't find '{}' section!", section),
+            Error::SymbolError(ref symbol) => write!(f, "Can't find '{}' symbol!", symbol),
             Error::CommonError(ref msg) => write!(f, "{}", msg),
             Error::NulError(ref error) => write!(f, "{}", error),
             Error::Utf8Error(ref error) => write!(f, "{}", error),
+            Error::IoError(ref error) => write!(f, "{}", error),
             Error::NullPointerError(ref error) => write!(f, "{}", error),
+            Error::MemoryError { vma } => write!(f, "Can't read memory at 0x{:x}!", vma),
+            Error::TruncatedInstruction { vma } => {
+                write!(f, "Truncated instruction at 0x{:x}!", vma)
+            }
+            Error::InvalidInstruction { vma } => write!(f, "Invalid instruction at 0x{:x}!", vma),
         }
     }
 }
@@ -59,3 +85,10 @@ impl From<std::str::Utf8Error> for Error {
         Error::Utf8Error(error)
     }
 }
+
+// Needed to use the ? operator on std::io::Write methods
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::IoError(error)
+    }
+}
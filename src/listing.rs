@@ -0,0 +1,432 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - listing.rs
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::rc::Rc;
+
+use bfd::Bfd;
+use opcodes::DisassembleInfo;
+use section::Section;
+use source::{SourceFiles, SourceLines};
+use symbol::{Symbol, SymbolTable, BSF_FUNCTION, BSF_GLOBAL};
+use symbolizer::BfdSymbolizer;
+use Error;
+
+/// Controls the layout of the listing, with the same defaults as objdump
+pub struct ListingOptions {
+    // Note: None uses the values provided by libopcodes
+    pub bytes_per_line: Option<usize>,
+    pub bytes_per_chunk: Option<usize>,
+    pub show_raw_bytes: bool,
+    pub skip_zeroes: usize,
+    pub skip_zeroes_at_end: usize,
+    // Print undecodable bytes as .byte and continue, like objdump
+    pub recovery: bool,
+}
+
+impl Default for ListingOptions {
+    fn default() -> ListingOptions {
+        ListingOptions {
+            bytes_per_line: None,
+            bytes_per_chunk: None,
+            show_raw_bytes: true,
+            skip_zeroes: 8,
+            skip_zeroes_at_end: 3,
+            recovery: true,
+        }
+    }
+}
+
+/// Writes disassembly listings laid out like `objdump -d`
+pub struct Listing<W: Write> {
+    writer: W,
+    options: ListingOptions,
+    // Interleave source lines, like `objdump -S`
+    source: Option<SourceFiles>,
+}
+
+impl<W: Write> Listing<W> {
+    pub fn new(writer: W) -> Listing<W> {
+        Listing::with_options(writer, ListingOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: ListingOptions) -> Listing<W> {
+        Listing {
+            writer,
+            options,
+            source: None,
+        }
+    }
+
+    /// Print the source lines that generated the instructions
+    pub fn set_source_files(&mut self, files: SourceFiles) {
+        self.source = Some(files);
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Write the file header, then all code sections in address order
+    pub fn write_bfd(&mut self, bfd: Bfd) -> Result<(), Error> {
+        write!(
+            self.writer,
+            "\n{}:     file format {}\n\n",
+            bfd.get_filename()?,
+            bfd.get_target_name()?
+        )?;
+
+        let symbols = bfd.get_symbols()?;
+        let mut sections = Vec::new();
+        for section in bfd.get_sections()? {
+            if section.is_code()? && section.get_size()? > 0 {
+                sections.push((section.get_vma()?, section));
+            }
+        }
+        sections.sort_by_key(|s| s.0);
+
+        // Note: libopcodes symbols are read once for all sections
+        let table = SymbolTable::sorted_from_bfd(bfd).ok().map(Rc::new);
+        for (_, section) in sections {
+            self.write_section_with_table(bfd, section, &symbols, table.clone())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_section(
+        &mut self,
+        bfd: Bfd,
+        section: Section,
+        symbols: &[Symbol],
+    ) -> Result<(), Error> {
+        let table = SymbolTable::sorted_from_bfd(bfd).ok().map(Rc::new);
+        self.write_section_with_table(bfd, section, symbols, table)
+    }
+
+    fn write_section_with_table(
+        &mut self,
+        bfd: Bfd,
+        section: Section,
+        symbols: &[Symbol],
+        table: Option<Rc<SymbolTable>>,
+    ) -> Result<(), Error> {
+        let name = section.get_name()?;
+        let start = section.get_vma()?;
+        let size = section.get_size()?;
+        let end = start + size;
+        write!(self.writer, "\nDisassembly of section {}:\n", name)?;
+
+        let mut info = DisassembleInfo::new()?;
+        info.configure_with_symbols(section, bfd, table)?;
+        info.configure_disassembler(bfd.disassembler()?)?;
+        info.init()?;
+        info.set_recovery(self.options.recovery);
+        let data = info.read_bytes(start, size)?;
+
+        // Branch targets are printed like objdump, i.e. `401126 <foo>`
+        let symbolizer = BfdSymbolizer::from_symbols(symbols.to_vec());
+        info.set_symbolizer(move |address| match symbolizer.lookup(address) {
+            Some((name, 0)) => format!("{:x} <{}>", address, name),
+            Some((name, offset)) => format!("{:x} <{}+0x{:x}>", address, name, offset),
+            None => format!("{:x}", address),
+        })?;
+
+        let labels = section_labels(section, symbols);
+        let width = (bfd.get_bits_per_address()? as usize / 4).max(8);
+        let skip = skipped_address_chars(start, end, width);
+
+        if !labels.contains_key(&start) {
+            write!(self.writer, "\n{:0w$x} <{}>:\n", start, name, w = width)?;
+        }
+
+        let lines = match self.source {
+            Some(_) => Some(SourceLines::new(bfd)?),
+            None => None,
+        };
+        let mut location = None;
+
+        let mut pc = start;
+        while pc < end {
+            if let Some(label) = labels.get(&pc) {
+                write!(self.writer, "\n{:0w$x} <{}>:\n", pc, label, w = width)?;
+            }
+
+            // Disassemble up to the next label, as objdump does
+            let limit = labels.range(pc + 1..).next().map(|l| *l.0).unwrap_or(end);
+
+            // Replace long runs of zeroes with an ellipsis
+            let offset = (pc - start) as usize;
+            let limit_offset = (limit - start) as usize;
+            let zeroes = data[offset..limit_offset]
+                .iter()
+                .take_while(|b| **b == 0)
+                .count();
+            if zeroes >= self.options.skip_zeroes
+                || (offset + zeroes == limit_offset
+                    && zeroes > 0
+                    && zeroes < self.options.skip_zeroes_at_end)
+            {
+                // Skip multiples of 4 to avoid running over the start of an instruction
+                let skipped = match offset + zeroes == limit_offset {
+                    true => zeroes,
+                    false => zeroes & !3,
+                };
+                writeln!(self.writer, "\t...")?;
+                pc += skipped as u64;
+                continue;
+            }
+
+            info.set_range(pc, limit)?;
+            let instruction = match info.disassemble() {
+                Some(Ok(i)) => i,
+                Some(Err(e)) => return Err(e),
+                None => break,
+            };
+
+            let bytes_per_line = match self.options.bytes_per_line {
+                Some(b) => b,
+                None => match info.bytes_per_line()? {
+                    0 => 4,
+                    b => b,
+                },
+            };
+            let bytes_per_chunk = match self.options.bytes_per_chunk {
+                Some(b) => b,
+                None => info.bytes_per_chunk()?.max(1),
+            };
+            let big_endian = info.is_display_big_endian()?;
+
+            if let Some(ref lines) = lines {
+                let current = lines.find(section, pc).map(|l| (l.file, l.line));
+                if current.is_some() && current != location {
+                    self.write_source(location.as_ref(), current.as_ref())?;
+                    location = current;
+                }
+            }
+
+            self.write_instruction(
+                pc,
+                instruction.bytes(),
+                &instruction.opcode,
+                (width, skip),
+                (bytes_per_line, bytes_per_chunk, big_endian),
+            )?;
+            pc += instruction.length;
+        }
+
+        Ok(())
+    }
+
+    // Print the lines since the previous location, or only the current one
+    fn write_source(
+        &mut self,
+        previous: Option<&(String, u32)>,
+        current: Option<&(String, u32)>,
+    ) -> Result<(), Error> {
+        let (file, line) = match current {
+            Some(&(ref file, line)) => (file, line),
+            None => return Ok(()),
+        };
+        let first = match previous {
+            Some(&(ref f, l)) if f == file && l < line && line - l <= 8 => l + 1,
+            _ => line,
+        };
+
+        let files = match self.source {
+            Some(ref mut f) => f,
+            None => return Ok(()),
+        };
+        let mut text = String::new();
+        for number in first..=line {
+            if let Some(source) = files.line(file, number) {
+                text.push_str(source);
+                text.push('\n');
+            }
+        }
+        self.writer.write_all(text.as_bytes())?;
+
+        Ok(())
+    }
+
+    fn write_instruction(
+        &mut self,
+        address: u64,
+        bytes: &[u8],
+        text: &str,
+        (width, skip): (usize, usize),
+        (bytes_per_line, bytes_per_chunk, big_endian): (usize, usize, bool),
+    ) -> Result<(), Error> {
+        write!(self.writer, "{}:\t", format_address(address, width, skip))?;
+        if !self.options.show_raw_bytes {
+            writeln!(self.writer, "{}", text)?;
+            return Ok(());
+        }
+
+        let first = bytes.len().min(bytes_per_line);
+        self.write_bytes(&bytes[..first], bytes_per_chunk, big_endian)?;
+
+        // Pad the raw bytes column
+        let mut printed = first;
+        while printed < bytes_per_line {
+            write!(self.writer, "{} ", "  ".repeat(bytes_per_chunk))?;
+            printed += bytes_per_chunk;
+        }
+        write!(self.writer, "\t{}", text)?;
+
+        // Long instructions continue on the next lines
+        let mut printed = first;
+        while printed < bytes.len() {
+            let next = bytes.len().min(printed + bytes_per_line);
+            write!(
+                self.writer,
+                "\n{}:\t",
+                format_address(address + printed as u64, width, skip)
+            )?;
+            self.write_bytes(&bytes[printed..next], bytes_per_chunk, big_endian)?;
+            printed = next;
+        }
+        writeln!(self.writer)?;
+
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8], chunk: usize, big_endian: bool) -> Result<(), Error> {
+        for chunk in bytes.chunks(chunk) {
+            if big_endian {
+                for byte in chunk {
+                    write!(self.writer, "{:02x}", byte)?;
+                }
+            } else {
+                for byte in chunk.iter().rev() {
+                    write!(self.writer, "{:02x}", byte)?;
+                }
+            }
+            write!(self.writer, " ")?;
+        }
+        Ok(())
+    }
+}
+
+// Pick one label per address, preferring global functions
+fn section_labels(section: Section, symbols: &[Symbol]) -> BTreeMap<u64, String> {
+    let mut labels: BTreeMap<u64, &Symbol> = BTreeMap::new();
+    for symbol in symbols {
+        if !symbol.is_label() || symbol.section != Some(section) {
+            continue;
+        }
+
+        let preferred = |s: &Symbol| s.flags & (BSF_GLOBAL | BSF_FUNCTION) != 0;
+        let replace = match labels.get(&symbol.address) {
+            Some(current) => !preferred(current) && preferred(symbol),
+            None => true,
+        };
+        if replace {
+            labels.insert(symbol.address, symbol);
+        }
+    }
+
+    labels
+        .into_iter()
+        .map(|(address, symbol)| (address, symbol.name.clone()))
+        .collect()
+}
+
+// Leading zeroes are discarded by chunks of 4, while keeping at least one
+fn skipped_address_chars(start: u64, end: u64, width: usize) -> usize {
+    let last = format!("{:0w$x}", end, w = width);
+    let zeroes = last.chars().take_while(|c| *c == '0').count();
+    if zeroes == width && start != 0 {
+        return 0;
+    }
+    match zeroes {
+        0 => 0,
+        z => (z - 1) & !3,
+    }
+}
+
+fn format_address(address: u64, width: usize, skip: usize) -> String {
+    let digits = format!("{:0w$x}", address, w = width);
+    let digits = &digits[skip.min(digits.len())..];
+
+    // Leading zeroes are replaced with spaces
+    let trimmed = digits.trim_start_matches('0');
+    let trimmed = if trimmed.is_empty() { "0" } else { trimmed };
+    format!("{:>w$}", trimmed, w = digits.len())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_format_address() {
+        use listing::{format_address, skipped_address_chars};
+
+        let skip = skipped_address_chars(0x1000, 0x16b40, 16);
+        assert_eq!(skip, 8);
+        assert_eq!(format_address(0x4000, 16, skip), "    4000");
+        assert_eq!(format_address(0, 8, 0), "       0");
+    }
+
+    #[test]
+    fn test_write_instruction() {
+        use listing::Listing;
+
+        let mut listing = Listing::new(Vec::new());
+        listing
+            .write_instruction(
+                0x4000,
+                &[0xf3, 0x0f, 0x1e, 0xfa],
+                "endbr64",
+                (16, 8),
+                (7, 1, false),
+            )
+            .unwrap();
+        listing
+            .write_instruction(
+                0x4004,
+                &[0x48, 0xb8, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11],
+                "movabs rax,0x1122334455667788",
+                (16, 8),
+                (7, 1, false),
+            )
+            .unwrap();
+        listing
+            .write_instruction(
+                0x400e,
+                &[0x1e, 0xff, 0x2f, 0xe1],
+                "bx\tlr",
+                (16, 8),
+                (4, 4, false),
+            )
+            .unwrap();
+
+        let output = String::from_utf8(listing.into_inner()).unwrap();
+        assert_eq!(
+            output,
+            "    4000:\tf3 0f 1e fa          \tendbr64\n\
+             \x20   4004:\t48 b8 88 77 66 55 44 \tmovabs rax,0x1122334455667788\n\
+             \x20   400b:\t33 22 11 \n\
+             \x20   400e:\te12fff1e \tbx\tlr\n"
+        );
+    }
+
+    #[test]
+    fn test_write_bfd() {
+        use listing::Listing;
+        use utils;
+
+        let bfd = match utils::open_test_binary() {
+            Some(b) => b,
+            None => return,
+        };
+        let section = bfd.get_section_by_name(".init").unwrap();
+
+        let mut listing = Listing::new(Vec::new());
+        listing.write_section(bfd, section, &[]).unwrap();
+
+        let output = String::from_utf8(listing.into_inner()).unwrap();
+        assert!(output.starts_with("\nDisassembly of section .init:\n"));
+        assert!(output.contains(" <.init>:\n"));
+    }
+}
@@ -0,0 +1,190 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - memory.rs
+
+use libc::{c_int, c_uint, c_ulong, EIO};
+
+use std::collections::BTreeMap;
+
+use bfd::Bfd;
+use helpers;
+use opcodes::{Callbacks, DisassembleInfoRaw};
+
+/// Provides the bytes that libopcodes reads while disassembling
+pub trait MemorySource {
+    /// Fill `buffer` with the bytes located at `vma`, or return false if any of them is unmapped
+    fn read(&mut self, vma: u64, buffer: &mut [u8]) -> bool;
+}
+
+// Any closure can be used as a memory source
+impl<F> MemorySource for F
+where
+    F: FnMut(u64, &mut [u8]) -> bool,
+{
+    fn read(&mut self, vma: u64, buffer: &mut [u8]) -> bool {
+        self(vma, buffer)
+    }
+}
+
+/// Sparse memory image built from several mapped regions
+#[derive(Default)]
+pub struct MemoryMap {
+    regions: BTreeMap<u64, Vec<u8>>,
+}
+
+impl MemoryMap {
+    pub fn new() -> MemoryMap {
+        MemoryMap {
+            regions: BTreeMap::new(),
+        }
+    }
+
+    /// Map `data` at `vma`, replacing any region that starts at the same address
+    pub fn add_region(&mut self, vma: u64, data: Vec<u8>) {
+        self.regions.insert(vma, data);
+    }
+}
+
+impl MemorySource for MemoryMap {
+    fn read(&mut self, vma: u64, buffer: &mut [u8]) -> bool {
+        // Copy the bytes region by region, as reads may span adjacent regions
+        let mut copied = 0;
+        while copied < buffer.len() {
+            let address = vma + copied as u64;
+            let (start, data) = match self.regions.range(..=address).next_back() {
+                Some(r) => r,
+                None => return false,
+            };
+
+            let offset = (address - start) as usize;
+            if offset >= data.len() {
+                return false;
+            }
+
+            let length = (data.len() - offset).min(buffer.len() - copied);
+            buffer[copied..copied + length].copy_from_slice(&data[offset..offset + length]);
+            copied += length;
+        }
+
+        true
+    }
+}
+
+/// Reads bytes from the sections of a bfd, using their VMA
+pub struct BfdMemory {
+    bfd: Bfd,
+}
+
+impl BfdMemory {
+    pub fn new(bfd: Bfd) -> BfdMemory {
+        BfdMemory { bfd }
+    }
+}
+
+impl MemorySource for BfdMemory {
+    fn read(&mut self, vma: u64, buffer: &mut [u8]) -> bool {
+        if self.bfd.raw().is_null() {
+            return false;
+        }
+
+        unsafe {
+            helpers::read_bfd_vma(
+                self.bfd.raw(),
+                vma,
+                buffer.as_mut_ptr(),
+                buffer.len() as u64,
+            )
+        }
+    }
+}
+
+// Called by libopcodes instead of its default buffer_read_memory()
+pub(crate) extern "C" fn read_memory(
+    memaddr: c_ulong,
+    myaddr: *mut u8,
+    length: c_uint,
+    info: *const DisassembleInfoRaw,
+) -> c_int {
+    let callbacks =
+        unsafe { helpers::get_disassemble_info_application_data(info) } as *mut Callbacks;
+    let source = match unsafe { callbacks.as_mut() }.and_then(|c| c.memory.as_mut()) {
+        Some(s) => s,
+        None => return EIO,
+    };
+    if myaddr.is_null() {
+        return EIO;
+    }
+
+    // Honour the end of the range, like buffer_read_memory() does
+    let stop = unsafe { helpers::get_disassemble_info_stop_vma(info) };
+    if stop != 0 && memaddr.saturating_add(length as u64) > stop {
+        unsafe { (*callbacks).crossed_stop = true };
+        return EIO;
+    }
+
+    let buffer = unsafe { std::slice::from_raw_parts_mut(myaddr, length as usize) };
+    match source.read(memaddr, buffer) {
+        true => 0,
+        false => EIO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_memory_map() {
+        use memory::{MemoryMap, MemorySource};
+
+        let mut memory = MemoryMap::new();
+        memory.add_region(0x1000, vec![0x90, 0x90]);
+        memory.add_region(0x1002, vec![0xc3]);
+        memory.add_region(0x2000, vec![0xcc]);
+
+        let mut buffer = [0; 3];
+        assert!(memory.read(0x1000, &mut buffer));
+        assert_eq!(buffer, [0x90, 0x90, 0xc3]);
+
+        assert!(!memory.read(0x1001, &mut buffer));
+        assert!(!memory.read(0x0fff, &mut buffer[..1]));
+        assert!(memory.read(0x2000, &mut buffer[..1]));
+    }
+
+    #[test]
+    fn test_disassemble_memory() {
+        use memory::MemoryMap;
+        use opcodes;
+        use opcodes::Endianness;
+        use utils;
+        use Error;
+
+        let bfd = match utils::test_bfd("i386:x86-64") {
+            Some(b) => b,
+            None => return,
+        };
+
+        let mut memory = MemoryMap::new();
+        memory.add_region(0x1000, vec![0x90, 0xc3]);
+
+        let mut di = opcodes::DisassembleInfo::new().unwrap();
+        di.init_memory_source(memory, bfd, 0x1000, Endianness::Little)
+            .unwrap();
+        assert_eq!(di.disassemble().unwrap().unwrap().opcode, "nop");
+        assert_eq!(di.disassemble().unwrap().unwrap().opcode, "ret");
+        match di.disassemble() {
+            Some(Err(Error::MemoryError { vma })) => assert_eq!(vma, 0x1002),
+            _ => assert!(false),
+        };
+
+        // Closures are memory sources too
+        let mut di = opcodes::DisassembleInfo::new().unwrap();
+        let source = |vma: u64, buffer: &mut [u8]| {
+            for (i, byte) in buffer.iter_mut().enumerate() {
+                *byte = if vma + i as u64 == 0x2000 { 0x90 } else { 0xc3 };
+            }
+            true
+        };
+        di.init_memory_source(source, bfd, 0x2000, Endianness::Little)
+            .unwrap();
+        assert_eq!(di.disassemble().unwrap().unwrap().opcode, "nop");
+        assert_eq!(di.disassemble().unwrap().unwrap().opcode, "ret");
+    }
+}
@@ -0,0 +1,118 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - mips.rs
+
+use mach::{bfd_mach_mips16, bfd_mach_mips_micromips};
+use symbol::Symbol;
+
+// ELF st_other flags, from include/elf/mips.h
+pub const STO_MIPS_ISA: u8 = 0xc0;
+pub const STO_MICROMIPS: u8 = 0x80;
+pub const STO_MIPS16: u8 = 0xf0;
+
+// ELF e_flags
+pub const EF_MIPS_ARCH_ASE_MICROMIPS: u64 = 0x0200_0000;
+pub const EF_MIPS_ARCH_ASE_M16: u64 = 0x0400_0000;
+
+/// Instruction set used by a MIPS function
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MipsIsaMode {
+    Standard,
+    Mips16,
+    MicroMips,
+}
+
+impl MipsIsaMode {
+    /// Use the symbol st_other flags, then the low bit of its address and the ELF e_flags
+    pub fn from_symbol(symbol: &Symbol, elf_flags: u64) -> MipsIsaMode {
+        if symbol.other & STO_MIPS16 == STO_MIPS16 {
+            return MipsIsaMode::Mips16;
+        }
+        if symbol.other & STO_MIPS_ISA == STO_MICROMIPS {
+            return MipsIsaMode::MicroMips;
+        }
+
+        if symbol.address & 1 == 0 {
+            MipsIsaMode::Standard
+        } else if elf_flags & EF_MIPS_ARCH_ASE_MICROMIPS != 0 {
+            MipsIsaMode::MicroMips
+        } else {
+            MipsIsaMode::Mips16
+        }
+    }
+
+    // libopcodes selects the decoder with info->mach
+    pub(crate) fn mach(self, standard: u64) -> u64 {
+        match self {
+            MipsIsaMode::Standard => standard,
+            MipsIsaMode::Mips16 => bfd_mach_mips16,
+            MipsIsaMode::MicroMips => bfd_mach_mips_micromips,
+        }
+    }
+
+    pub(crate) fn is_compressed_mach(mach: u64) -> bool {
+        mach == bfd_mach_mips16 || mach == bfd_mach_mips_micromips
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_isa_mode() {
+        use mips::{MipsIsaMode, EF_MIPS_ARCH_ASE_MICROMIPS, STO_MICROMIPS, STO_MIPS16};
+        use symbol::{Symbol, BSF_FUNCTION};
+
+        let symbol = |address: u64, other: u8| Symbol {
+            name: "f".to_string(),
+            address,
+            flags: BSF_FUNCTION,
+            defined: true,
+            size: 0,
+            section: None,
+            other,
+        };
+
+        assert_eq!(
+            MipsIsaMode::from_symbol(&symbol(0x1000, 0), 0),
+            MipsIsaMode::Standard
+        );
+        assert_eq!(
+            MipsIsaMode::from_symbol(&symbol(0x1000, STO_MIPS16), 0),
+            MipsIsaMode::Mips16
+        );
+        assert_eq!(
+            MipsIsaMode::from_symbol(&symbol(0x1000, STO_MICROMIPS), 0),
+            MipsIsaMode::MicroMips
+        );
+        assert_eq!(
+            MipsIsaMode::from_symbol(&symbol(0x1001, 0), 0),
+            MipsIsaMode::Mips16
+        );
+        assert_eq!(
+            MipsIsaMode::from_symbol(&symbol(0x1001, 0), EF_MIPS_ARCH_ASE_MICROMIPS),
+            MipsIsaMode::MicroMips
+        );
+    }
+
+    #[test]
+    fn test_mips16_buffer() {
+        use mips::MipsIsaMode;
+        use opcodes::Endianness;
+        use utils;
+
+        // Standard nop, then the MIPS16 jr ra
+        let buffer = [0x00, 0x00, 0x00, 0x00, 0xe8, 0x20];
+        let mut info = match utils::test_buffer("mips:isa32", &buffer, 0x1000, Endianness::Big) {
+            Some(i) => i,
+            None => return,
+        };
+
+        info.set_isa_modes(vec![
+            (0x1000, MipsIsaMode::Standard),
+            (0x1004, MipsIsaMode::Mips16),
+        ]);
+        assert_eq!(info.disassemble().unwrap().unwrap().opcode, "nop");
+        let instruction = info.disassemble().unwrap().unwrap();
+        assert_eq!(instruction.length, 2);
+        assert_eq!(instruction.opcode, "jr\tra");
+    }
+}
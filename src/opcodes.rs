@@ -1,14 +1,23 @@
 // Guillaume Valadon <guillaume@valadon.net>
 // binutils libopcodes bindings - opcodes.rs
 
-use libc::{c_uint, c_ulong, uintptr_t};
+use libc::{c_long, c_uint, c_ulong, c_void, uintptr_t};
 use std;
+use std::borrow::Cow;
+use std::ffi::CStr;
+use std::rc::Rc;
 
 use super::Error;
+use arch::Machine;
+use batch::InstructionBatch;
 use bfd::{Bfd, BfdRaw};
 use helpers;
-use instruction::{get_instruction, Instruction};
+use instruction::{get_instruction, get_opcode, Instruction, InstructionType};
+use memory::{read_memory, MemorySource};
+use mips::MipsIsaMode;
 use section::Section;
+use symbol::{SymbolTable, BSF_FUNCTION};
+use symbolizer::{print_address, Symbolizer};
 use utils;
 
 extern "C" {
@@ -24,13 +33,56 @@ extern "C" {
 
 pub type DisassemblerFunction = dyn Fn(c_ulong, &DisassembleInfo) -> c_ulong;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Endianness {
+    Little,
+    Big,
+    // Big-endian data with little-endian instructions, as used by ARM BE8
+    Be8,
+}
+
+impl Endianness {
+    pub fn is_big_endian(self) -> bool {
+        self != Endianness::Little
+    }
+
+    pub fn is_big_endian_code(self) -> bool {
+        self == Endianness::Big
+    }
+}
+
 pub(crate) enum DisassembleInfoRaw {}
 
+// Rust objects used by libopcodes callbacks, stored in info->application_data
+#[derive(Default)]
+pub(crate) struct Callbacks {
+    pub(crate) memory: Option<Box<dyn MemorySource>>,
+    pub(crate) symbolizer: Option<Box<dyn Symbolizer>>,
+    // Set by set_print_address_func(), replaces the symbolizer
+    pub(crate) print_address: Option<extern "C" fn(c_ulong, *const uintptr_t)>,
+    // Set when the memory source refused a read that crosses stop_vma
+    pub(crate) crossed_stop: bool,
+    // Addresses printed while disassembling the current instruction
+    pub(crate) addresses: Vec<u64>,
+}
+
 pub struct DisassembleInfo {
     info: *const DisassembleInfoRaw,
     free_section: bool,
     disassembler: Option<Box<DisassemblerFunction>>,
     pc: u64,
+    callbacks: *mut Callbacks,
+    // Range set by set_range(), as [start, end)
+    range: Option<(u64, u64)>,
+    // Sorted symbols, used by libopcodes to find mapping symbols
+    symbol_table: Option<Rc<SymbolTable>>,
+    // MIPS ISA modes, sorted by the start address of the functions
+    isa_modes: Vec<(u64, MipsIsaMode)>,
+    standard_mach: u64,
+    // Skip undecodable bytes instead of reporting errors
+    recovery: bool,
+    // Bytes skipped after an undecodable instruction, 0 if the disassembler did not tell
+    resync_length: u64,
 }
 
 impl DisassembleInfo {
@@ -40,6 +92,13 @@ impl DisassembleInfo {
             free_section: false,
             disassembler: None,
             pc: 0,
+            callbacks: std::ptr::null_mut(),
+            range: None,
+            symbol_table: None,
+            isa_modes: Vec::new(),
+            standard_mach: 0,
+            recovery: false,
+            resync_length: 0,
         }
     }
 
@@ -56,6 +115,13 @@ impl DisassembleInfo {
             free_section: false,
             disassembler: None,
             pc: 0,
+            callbacks: Box::into_raw(Box::new(Callbacks::default())),
+            range: None,
+            symbol_table: None,
+            isa_modes: Vec::new(),
+            standard_mach: 0,
+            recovery: false,
+            resync_length: 0,
         })
     }
 
@@ -63,7 +129,56 @@ impl DisassembleInfo {
         self.info
     }
 
-    pub fn configure(&self, section: Section, bfd: Bfd) -> Result<(), Error> {
+    fn callbacks(&mut self) -> Result<&mut Callbacks, Error> {
+        utils::check_null_pointer(self.callbacks, "callbacks pointer is null!")?;
+        Ok(unsafe { &mut *self.callbacks })
+    }
+
+    // Note: must be called each time that init_disassemble_info() resets the structure
+    fn attach_callbacks(&self) {
+        if self.info.is_null() {
+            return;
+        }
+
+        if let Some(ref table) = self.symbol_table {
+            unsafe {
+                helpers::set_disassemble_info_symbol_table(
+                    self.info,
+                    table.raw(),
+                    table.len() as c_long,
+                )
+            };
+        }
+
+        if self.callbacks.is_null() {
+            return;
+        }
+
+        let print_function = unsafe { (*self.callbacks).print_address }.unwrap_or(print_address);
+        unsafe {
+            helpers::set_disassemble_info_application_data(
+                self.info,
+                self.callbacks as *mut c_void,
+            );
+            helpers::set_print_address_func(self.info, print_function);
+        }
+    }
+
+    /// Disassemble a section, using the sorted symbols of the bfd to find mapping symbols
+    // Note: the section is disassembled without symbols if they can't be read
+    pub fn configure(&mut self, section: Section, bfd: Bfd) -> Result<(), Error> {
+        let symbols = SymbolTable::sorted_from_bfd(bfd).ok().map(Rc::new);
+        self.configure_with_symbols(section, bfd, symbols)
+    }
+
+    /// Same as configure(), with symbols from SymbolTable::sorted_from_bfd(), i.e. to read them
+    /// once for all sections
+    pub fn configure_with_symbols(
+        &mut self,
+        section: Section,
+        bfd: Bfd,
+        symbols: Option<Rc<SymbolTable>>,
+    ) -> Result<(), Error> {
         utils::check_null_pointer(self.info, "info pointer is null!")?;
         utils::check_null_pointer(self.raw(), "section pointer is null!")?;
         utils::check_null_pointer(bfd.raw(), "bfd pointer is null!")?;
@@ -73,17 +188,80 @@ impl DisassembleInfo {
                 "Error while calling configure_disassemble_info() !".to_string(),
             ));
         }
+        self.range = None;
+
+        // Note: ARM and AArch64 use mapping symbols to switch between code and data
+        match symbols {
+            Some(table) => self.set_symbol_table(bfd, table),
+            None => {
+                self.symbol_table = None;
+                Ok(())
+            }
+        }
+    }
+
+    /// Give the symbols of a bfd to libopcodes, i.e. to disassemble a buffer read from it
+    pub fn configure_symbols(&mut self, bfd: Bfd) -> Result<(), Error> {
+        utils::check_null_pointer(self.info, "info pointer is null!")?;
+
+        let table = SymbolTable::sorted_from_bfd(bfd)?;
+        self.set_symbol_table(bfd, Rc::new(table))
+    }
+
+    fn set_symbol_table(&mut self, bfd: Bfd, table: Rc<SymbolTable>) -> Result<(), Error> {
+        // MIPS functions may use the MIPS16 or microMIPS instruction sets
+        if bfd.get_arch_name()?.starts_with("mips") {
+            let elf_flags = bfd.get_elf_flags()?;
+            let modes = table
+                .symbols()?
+                .iter()
+                .filter(|s| s.is_label() && s.flags & BSF_FUNCTION != 0)
+                .map(|s| (s.address & !1, MipsIsaMode::from_symbol(s, elf_flags)))
+                .collect();
+            self.set_isa_modes(modes);
+        }
+
+        self.symbol_table = Some(table);
+        self.attach_callbacks();
+
+        Ok(())
+    }
+
+    /// Decode MIPS code starting at each address with the given instruction set
+    pub fn set_isa_modes(&mut self, mut modes: Vec<(u64, MipsIsaMode)>) {
+        modes.sort_by_key(|m| m.0);
+        self.isa_modes = modes;
+    }
+
+    pub fn set_mips_isa_mode(&mut self, mode: MipsIsaMode) -> Result<(), Error> {
+        utils::check_null_pointer(self.info, "info pointer is null!")?;
+
+        // Note: the standard mach is the one configured from the bfd or by the user
+        let mach = unsafe { helpers::get_disassemble_info_mach(self.info) };
+        let standard = match MipsIsaMode::is_compressed_mach(mach) {
+            true => self.standard_mach,
+            false => mach,
+        };
+        self.standard_mach = standard;
+        unsafe { helpers::set_disassemble_info_mach(self.info, mode.mach(standard)) };
 
         Ok(())
     }
 
-    pub fn init_buffer(&mut self, buffer: &[u8], bfd: Bfd, offset: u64) -> Result<(), Error> {
-        let disassemble_fn = match bfd.raw_disassembler(bfd.arch_mach.0, false, bfd.arch_mach.1) {
+    pub fn init_buffer(
+        &mut self,
+        buffer: &[u8],
+        bfd: Bfd,
+        offset: u64,
+        endian: Endianness,
+    ) -> Result<(), Error> {
+        let machine = bfd.get_machine()?;
+        let disassemble_fn = match bfd.raw_disassembler(machine, endian.is_big_endian()) {
             Ok(f) => f,
             Err(e) => return Err(e),
         };
 
-        self.configure_buffer(bfd.arch_mach.0, bfd.arch_mach.1, buffer, offset)?;
+        self.configure_buffer(machine, buffer, offset, endian)?;
         self.configure_disassembler(disassemble_fn)?;
         self.init()?;
 
@@ -92,10 +270,10 @@ impl DisassembleInfo {
 
     pub fn configure_buffer(
         &mut self,
-        arch: c_uint,
-        mach: c_ulong,
+        machine: Machine,
         buffer: &[u8],
         offset: u64,
+        endian: Endianness,
     ) -> Result<(), Error> {
         utils::check_null_pointer(self.info, "info pointer is null!")?;
 
@@ -109,11 +287,14 @@ impl DisassembleInfo {
                     "buffer length is 0!".to_string(),
                 ));
             };
-            
+
+            let (arch, mach) = machine.raw();
             let section = helpers::configure_disassemble_info_buffer(
                 self.info,
                 arch,
                 mach,
+                endian.is_big_endian(),
+                endian.is_big_endian_code(),
                 offset,
                 len as u64,
                 ptr,
@@ -127,9 +308,64 @@ impl DisassembleInfo {
 
             self.free_section = true;
         }
+        self.range = None;
+        self.callbacks()?.memory = None;
+        self.attach_callbacks();
+        Ok(())
+    }
+
+    pub fn init_memory_source<M: MemorySource + 'static>(
+        &mut self,
+        source: M,
+        bfd: Bfd,
+        vma: u64,
+        endian: Endianness,
+    ) -> Result<(), Error> {
+        let machine = bfd.get_machine()?;
+        let disassemble_fn = bfd.raw_disassembler(machine, endian.is_big_endian())?;
+
+        self.configure_memory_source(machine, source, vma, endian)?;
+        self.configure_disassembler(disassemble_fn)?;
+        self.init()?;
+
+        Ok(())
+    }
+
+    pub fn configure_memory_source<M: MemorySource + 'static>(
+        &mut self,
+        machine: Machine,
+        source: M,
+        vma: u64,
+        endian: Endianness,
+    ) -> Result<(), Error> {
+        utils::check_null_pointer(self.info, "info pointer is null!")?;
+
+        self.callbacks()?.memory = Some(Box::new(source));
+
+        let (arch, mach) = machine.raw();
+        let section = unsafe {
+            helpers::configure_disassemble_info_memory(
+                self.info,
+                arch,
+                mach,
+                endian.is_big_endian(),
+                endian.is_big_endian_code(),
+                vma,
+                self.callbacks as *mut c_void,
+                read_memory,
+            )
+        };
+        if section.is_null() {
+            return Err(Error::DisassembleInfoError(
+                "helpers::configure_disassemble_info_memory() malloc error!".to_string(),
+            ));
+        }
+
+        self.free_section = true;
+        self.range = None;
+        self.attach_callbacks();
         Ok(())
     }
-    
 
     pub fn init(&self) -> Result<(), Error> {
         utils::check_null_pointer(self.info, "info pointer is null!")?;
@@ -138,12 +374,27 @@ impl DisassembleInfo {
         Ok(())
     }
 
+    /// Print addresses with a C function, which is kept until set_symbolizer() is called
+    // Note: addresses printed by this function are not used to find branch targets
     pub fn set_print_address_func(
-        &self,
+        &mut self,
         print_function: extern "C" fn(c_ulong, *const uintptr_t),
     ) -> Result<(), Error> {
         utils::check_null_pointer(self.info, "info pointer is null!")?;
-        unsafe { helpers::set_print_address_func(self.info, print_function) }
+
+        self.callbacks()?.print_address = Some(print_function);
+        self.attach_callbacks();
+
+        Ok(())
+    }
+
+    pub fn set_symbolizer<S: Symbolizer + 'static>(&mut self, symbolizer: S) -> Result<(), Error> {
+        utils::check_null_pointer(self.info, "info pointer is null!")?;
+
+        let callbacks = self.callbacks()?;
+        callbacks.symbolizer = Some(Box::new(symbolizer));
+        callbacks.print_address = None;
+        self.attach_callbacks();
 
         Ok(())
     }
@@ -169,7 +420,111 @@ impl DisassembleInfo {
         Ok(())
     }
 
+    /// Emit `.byte` pseudo-instructions for invalid or truncated instructions, then continue
+    pub fn set_recovery(&mut self, enabled: bool) {
+        self.recovery = enabled;
+    }
+
     pub fn disassemble<'a>(&mut self) -> Option<Result<Instruction<'a>, Error>> {
+        match self.decode() {
+            Some(Err(Error::InvalidInstruction { vma }))
+            | Some(Err(Error::TruncatedInstruction { vma }))
+                if self.recovery =>
+            {
+                Some(self.skip_bytes(vma))
+            }
+            result => result,
+        }
+    }
+
+    // Number of undecodable bytes to skip
+    fn skipped_length(&self, vma: u64) -> u64 {
+        let (_, end) = self.buffer_bounds();
+        let length = match self.resync_length {
+            0 => self.min_instruction_size(),
+            length => length,
+        };
+        length.min(end - vma)
+    }
+
+    // Skip the undecodable bytes, and describe them
+    fn skip_bytes<'a>(&mut self, vma: u64) -> Result<Instruction<'a>, Error> {
+        let length = self.skipped_length(vma);
+        let bytes = self.instruction_bytes(vma, length)?.into_owned();
+
+        let values: Vec<_> = bytes.iter().map(|b| format!("0x{:02x}", b)).collect();
+        let mut instruction = Instruction::empty_with_error(None);
+        instruction.offset = vma;
+        instruction.length = length;
+        instruction.opcode = format!(".byte {}", values.join(", "));
+        instruction.insn_type = Some(InstructionType::NonInstruction);
+        instruction.set_bytes(bytes);
+
+        self.pc = vma + length;
+        Ok(instruction)
+    }
+
+    // Used when the disassembler failed before telling the size of the instruction
+    fn min_instruction_size(&self) -> u64 {
+        let name = unsafe { helpers::get_disassemble_info_arch_name(self.info) };
+        if name.is_null() {
+            return 1;
+        }
+
+        let mach = unsafe { helpers::get_disassemble_info_mach(self.info) };
+        match unsafe { CStr::from_ptr(name) }.to_str().unwrap_or("") {
+            "mips" if MipsIsaMode::is_compressed_mach(mach) => 2,
+            "aarch64" | "alpha" | "hppa" | "loongarch" | "mips" | "powerpc" | "rs6000"
+            | "sparc" => 4,
+            // Note: the smallest size of all modes, i.e. Thumb
+            "arm" | "avr" | "m68k" | "msp430" | "riscv" | "s390" | "sh" => 2,
+            _ => 1,
+        }
+    }
+
+    fn decode<'a>(&mut self) -> Option<Result<Instruction<'a>, Error>> {
+        let count = match self.decode_next()? {
+            Ok(count) => count,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let instruction = match get_instruction(self.pc, count) {
+            Ok(mut i) => match self.instruction_bytes(self.pc, count) {
+                Ok(bytes) => {
+                    i.set_bytes(bytes.into_owned());
+                    let (insn_type, target) = self.instruction_type(&i.opcode);
+                    i.insn_type = insn_type;
+                    i.target = target;
+                    i.branch_delay = self.branch_delay();
+                    if let Some(callbacks) = unsafe { self.callbacks.as_ref() } {
+                        i.set_addresses(callbacks.addresses.clone());
+                    }
+                    Ok(i)
+                }
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        if instruction.is_ok() {
+            self.pc += count;
+        }
+        Some(instruction)
+    }
+
+    // Decode the instruction located at pc, and leave its text in CURRENT_OPCODE
+    fn decode_next(&mut self) -> Option<Result<u64, Error>> {
+        // Switch the ISA mode when entering a MIPS function
+        let mode = match self.isa_modes.binary_search_by_key(&self.pc, |m| m.0) {
+            Ok(i) => Some(self.isa_modes[i].1),
+            Err(0) => None,
+            Err(i) => Some(self.isa_modes[i - 1].1),
+        };
+        if let Some(mode) = mode {
+            if let Err(e) = self.set_mips_isa_mode(mode) {
+                return Some(Err(e));
+            }
+        }
+
         let f = match self.disassembler {
             Some(ref f) => f,
             None => {
@@ -179,14 +534,311 @@ impl DisassembleInfo {
             }
         };
 
+        // The buffer, or the range, is exhausted
+        let (start, end) = self.buffer_bounds();
+        if self.pc < start || self.pc >= end {
+            return None;
+        }
+
+        let has_memory_source = self.has_memory_source();
+        if has_memory_source {
+            unsafe { (*self.callbacks).crossed_stop = false };
+        }
+        if !self.callbacks.is_null() {
+            unsafe { (*self.callbacks).addresses.clear() };
+        }
+        unsafe { helpers::reset_disassemble_info_insn_info(self.info) };
+        helpers::CURRENT_BAD_INSTRUCTION.with(|bad| bad.set(false));
+
+        // Disassemblers set bytes_per_chunk to the size of the unit they decode, i.e. 2 for Thumb
+        let chunk = unsafe { helpers::get_disassemble_info_bytes_per_chunk(self.info) };
+        unsafe { helpers::set_disassemble_info_bytes_per_chunk(self.info, 0) };
+
         let count = f(self.pc, self);
-        if count == 4_294_967_295 {
+        let memory_error = helpers::CURRENT_MEMORY_ERROR.with(|error| error.get());
+        let bad = helpers::CURRENT_BAD_INSTRUCTION.with(|bad| bad.get());
+
+        let reported_chunk = unsafe { helpers::get_disassemble_info_bytes_per_chunk(self.info) };
+        if reported_chunk == 0 {
+            unsafe { helpers::set_disassemble_info_bytes_per_chunk(self.info, chunk) };
+        }
+        self.resync_length = u64::from(reported_chunk);
+
+        if count == 4_294_967_295 || count == 0 {
+            // Note: buffer reads only fail past the end of the buffer or of the range
+            let truncated = !has_memory_source || unsafe { (*self.callbacks).crossed_stop };
+
+            // Discard the partial opcode
+            let _ = get_opcode();
+            return Some(Err(match memory_error {
+                Some(vma) if vma >= end || truncated => {
+                    Error::TruncatedInstruction { vma: self.pc }
+                }
+                Some(vma) => Error::MemoryError { vma },
+                None => Error::InvalidInstruction { vma: self.pc },
+            }));
+        }
+
+        if self.pc + count > end {
+            let _ = get_opcode();
+            return Some(Err(Error::TruncatedInstruction { vma: self.pc }));
+        }
+
+        // The disassembler consumed the bytes of the undecodable encoding
+        if bad {
+            let _ = get_opcode();
+            self.resync_length = count;
+            return Some(Err(Error::InvalidInstruction { vma: self.pc }));
+        }
+
+        Some(Ok(count))
+    }
+
+    pub fn bytes_per_line(&self) -> Result<usize, Error> {
+        utils::check_null_pointer(self.info, "info pointer is null!")?;
+
+        Ok(unsafe { helpers::get_disassemble_info_bytes_per_line(self.info) } as usize)
+    }
+
+    pub fn bytes_per_chunk(&self) -> Result<usize, Error> {
+        utils::check_null_pointer(self.info, "info pointer is null!")?;
+
+        Ok(unsafe { helpers::get_disassemble_info_bytes_per_chunk(self.info) } as usize)
+    }
+
+    pub fn is_display_big_endian(&self) -> Result<bool, Error> {
+        utils::check_null_pointer(self.info, "info pointer is null!")?;
+
+        Ok(unsafe { helpers::is_disassemble_info_display_big_endian(self.info) })
+    }
+
+    pub fn read_bytes(&self, vma: u64, length: u64) -> Result<Vec<u8>, Error> {
+        utils::check_null_pointer(self.info, "info pointer is null!")?;
+
+        let mut bytes = vec![0; length as usize];
+        let status = unsafe {
+            helpers::read_disassemble_info_memory(
+                self.info,
+                vma,
+                bytes.as_mut_ptr(),
+                length as c_uint,
+            )
+        };
+        if status != 0 {
+            return Err(Error::MemoryError { vma });
+        }
+
+        Ok(bytes)
+    }
+
+    // Return the bytes located in the buffer, without calling libopcodes
+    fn buffer_slice(&self, vma: u64, length: u64) -> Option<&[u8]> {
+        if self.info.is_null() || self.has_memory_source() {
             return None;
         }
 
-        let instruction = get_instruction(self.pc, count);
-        self.pc += count;
-        Some(instruction)
+        // Note: vma counts octets_per_byte bytes, i.e. on TI C54x
+        let buffer = unsafe { helpers::get_disassemble_info_buffer(self.info) };
+        let octets_per_byte = unsafe { helpers::get_disassemble_info_octets_per_byte(self.info) };
+        if buffer.is_null() || octets_per_byte > 1 {
+            return None;
+        }
+
+        let (start, end) = self.source_bounds();
+        if vma < start || vma.checked_add(length)? > end {
+            return None;
+        }
+        let data = unsafe { std::slice::from_raw_parts(buffer, (end - start) as usize) };
+        let offset = (vma - start) as usize;
+        Some(&data[offset..offset + length as usize])
+    }
+
+    // Return the bytes of an instruction, from the buffer or from the memory source
+    fn instruction_bytes(&self, vma: u64, length: u64) -> Result<Cow<'_, [u8]>, Error> {
+        match self.buffer_slice(vma, length) {
+            Some(bytes) => Ok(Cow::Borrowed(bytes)),
+            None => self.read_bytes(vma, length).map(Cow::Owned),
+        }
+    }
+
+    pub fn set_range(&mut self, start: u64, end: u64) -> Result<(), Error> {
+        utils::check_null_pointer(self.info, "info pointer is null!")?;
+        if self.disassembler.is_none() {
+            return Err(Error::CommonError(
+                "disassembler not configured!".to_string(),
+            ));
+        }
+
+        // Note: the previous range is kept if the new one is invalid
+        let (buffer_start, buffer_end) = self.source_bounds();
+        if start >= end || start < buffer_start || start >= buffer_end {
+            return Err(Error::CommonError(format!(
+                "Invalid range 0x{:x}-0x{:x}!",
+                start, end
+            )));
+        }
+
+        // libopcodes won't read bytes located at, or after, stop_vma
+        // Note: end is never 0, which libopcodes uses to disable stop_vma
+        self.range = Some((start, end));
+        unsafe { helpers::set_disassemble_info_stop_vma(self.info, end) };
+        self.pc = start;
+
+        Ok(())
+    }
+
+    pub fn disassemble_range(&mut self, start: u64, end: u64) -> Instruction<'_> {
+        match self.set_range(start, end) {
+            Ok(_) => Instruction::from_info(self),
+            Err(e) => Instruction::empty_with_error(Some(e)),
+        }
+    }
+
+    /// Disassemble the remaining instructions at once, emitting `.byte` for undecodable bytes
+    pub fn disassemble_all(&mut self) -> Result<Vec<Instruction<'static>>, Error> {
+        let (_, end) = self.buffer_bounds();
+        let mut instructions = Vec::with_capacity((end.saturating_sub(self.pc) / 4) as usize);
+
+        let recovery = self.recovery;
+        self.recovery = true;
+        let mut result = Ok(());
+        while let Some(instruction) = self.disassemble() {
+            match instruction {
+                Ok(i) => instructions.push(i),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        self.recovery = recovery;
+
+        result.map(|_| instructions)
+    }
+
+    /// Disassemble the remaining instructions at once, and store them in shared buffers
+    ///
+    /// Unlike disassemble_all(), no memory is allocated for each instruction.
+    pub fn disassemble_batch(&mut self) -> Result<InstructionBatch, Error> {
+        self.disassemble_batch_until(u64::MAX)
+    }
+
+    // Same as disassemble_batch(), but the last instruction is the one that contains stop
+    pub(crate) fn disassemble_batch_until(&mut self, stop: u64) -> Result<InstructionBatch, Error> {
+        let (_, end) = self.buffer_bounds();
+        let mut batch = InstructionBatch::with_buffer_size(end.min(stop).saturating_sub(self.pc));
+
+        while self.pc < stop {
+            let vma = self.pc;
+            match self.decode_next() {
+                Some(Ok(count)) => {
+                    let (insn_type, target) = self.instruction_type(batch.push_opcode());
+                    let bytes = self.instruction_bytes(vma, count)?;
+                    let addresses = match unsafe { self.callbacks.as_ref() } {
+                        Some(c) => &c.addresses[..],
+                        None => &[],
+                    };
+                    batch.push(
+                        vma,
+                        &bytes,
+                        addresses,
+                        insn_type,
+                        target,
+                        self.branch_delay(),
+                    );
+                    self.pc += count;
+                }
+                Some(Err(Error::InvalidInstruction { vma }))
+                | Some(Err(Error::TruncatedInstruction { vma })) => {
+                    let length = self.skipped_length(vma);
+                    let bytes = self.instruction_bytes(vma, length)?;
+                    batch.push_data(&bytes);
+                    batch.push(
+                        vma,
+                        &bytes,
+                        &[],
+                        Some(InstructionType::NonInstruction),
+                        None,
+                        0,
+                    );
+                    self.pc = vma + length;
+                }
+                Some(Err(e)) => return Err(e),
+                None => break,
+            }
+        }
+
+        Ok(batch)
+    }
+
+    // Classify the last disassembled instruction and find its branch target
+    fn instruction_type(&self, opcode: &str) -> (Option<InstructionType>, Option<u64>) {
+        let addresses = match unsafe { self.callbacks.as_ref() } {
+            Some(c) => &c.addresses[..],
+            None => &[],
+        };
+
+        if unsafe { helpers::is_disassemble_info_insn_info_valid(self.info) } {
+            let insn_type = InstructionType::from_raw(unsafe {
+                helpers::get_disassemble_info_insn_type(self.info)
+            });
+            let target = match unsafe { helpers::get_disassemble_info_target(self.info) } {
+                0 => None,
+                target => Some(target),
+            };
+            let target = match insn_type {
+                Some(t) if t.is_branch() => target.or_else(|| addresses.first().cloned()),
+                _ => target,
+            };
+            return (insn_type, target);
+        }
+
+        if !unsafe { helpers::is_disassemble_info_x86(self.info) } {
+            return (None, None);
+        }
+
+        // Note: memory operands are indirect branches, even if an address is printed
+        let insn_type = InstructionType::from_x86_opcode(opcode);
+        let target = match insn_type.is_branch() && !opcode.contains('[') {
+            true => addresses.first().cloned(),
+            false => None,
+        };
+        (Some(insn_type), target)
+    }
+
+    fn branch_delay(&self) -> u32 {
+        match unsafe { helpers::is_disassemble_info_insn_info_valid(self.info) } {
+            true => unsafe { helpers::get_disassemble_info_branch_delay_insns(self.info) },
+            false => 0,
+        }
+    }
+
+    pub(crate) fn current_vma(&self) -> u64 {
+        self.pc
+    }
+
+    fn has_memory_source(&self) -> bool {
+        !self.callbacks.is_null() && unsafe { (*self.callbacks).memory.is_some() }
+    }
+
+    // Bounds of the buffer, or of the memory source
+    fn source_bounds(&self) -> (u64, u64) {
+        // Memory sources report unmapped addresses themselves
+        if self.has_memory_source() {
+            return (0, u64::MAX);
+        }
+
+        let start = unsafe { helpers::get_disassemble_info_buffer_vma(self.info) };
+        let length = unsafe { helpers::get_disassemble_info_buffer_length(self.info) };
+        (start, start.saturating_add(length))
+    }
+
+    pub(crate) fn buffer_bounds(&self) -> (u64, u64) {
+        let (start, end) = self.source_bounds();
+        match self.range {
+            Some((_, stop)) => (start, end.min(stop)),
+            None => (start, end),
+        }
     }
 }
 
@@ -198,6 +850,10 @@ impl Drop for DisassembleInfo {
             }
             self.info = std::ptr::null();
         }
+        if !self.callbacks.is_null() {
+            unsafe { drop(Box::from_raw(self.callbacks)) };
+            self.callbacks = std::ptr::null_mut();
+        }
     }
 }
 
@@ -208,21 +864,25 @@ mod tests {
         use bfd;
         use opcodes;
         use std;
+        use utils;
 
         let mut di = opcodes::DisassembleInfo::empty();
         assert_eq!(di.info, std::ptr::null());
 
-        let mut bfd = bfd::Bfd::empty();
-        match di.init_buffer(&[0x90], bfd, 0) {
+        match di.init_buffer(&[0x90], bfd::Bfd::empty(), 0, opcodes::Endianness::Little) {
             Ok(_) => assert!(false),
             Err(_) => assert!(true),
         };
 
-        let _ = bfd.set_arch_mach("i386:x86-64");
-        let _ = di.configure_buffer(bfd.arch_mach.0, bfd.arch_mach.1, &[0x90], 1);
+        let bfd = match utils::test_bfd("i386:x86-64") {
+            Some(b) => b,
+            None => return,
+        };
+        let machine = bfd.get_machine().unwrap();
+        let _ = di.configure_buffer(machine, &[0x90], 1, opcodes::Endianness::Little);
 
         let disassemble_fn = bfd
-            .raw_disassembler(bfd.arch_mach.0, false, bfd.arch_mach.1)
+            .raw_disassembler(bfd.get_machine().unwrap(), false)
             .unwrap();
         let _ = di.configure_disassembler(disassemble_fn);
     }
@@ -234,6 +894,7 @@ mod tests {
         use opcodes;
         use section;
         use std;
+        use utils;
 
         let mut di = opcodes::DisassembleInfo::new().unwrap();
         assert_ne!(di.info, std::ptr::null());
@@ -250,26 +911,318 @@ mod tests {
             Err(_) => assert!(true),
         }
 
-        let mut bfd = bfd::Bfd::empty();
-        let _ = bfd.set_arch_mach("i386:x86-64");
-        let _ = di.configure_buffer(bfd.arch_mach.0, bfd.arch_mach.1, &[], 0);
+        let bfd = match utils::test_bfd("i386:x86-64") {
+            Some(b) => b,
+            None => return,
+        };
+        let machine = bfd.get_machine().unwrap();
+        let _ = di.configure_buffer(machine, &[], 0, opcodes::Endianness::Little);
     }
 
     #[test]
     fn test_configure_disassembler() {
-        use bfd;
         use opcodes;
         use std;
+        use utils;
 
         let mut di = opcodes::DisassembleInfo::new().unwrap();
         assert_ne!(di.info, std::ptr::null());
 
-        let mut bfd = bfd::Bfd::empty();
-        let _ = bfd.set_arch_mach("i386:x86-64");
+        let bfd = match utils::test_bfd("i386:x86-64") {
+            Some(b) => b,
+            None => return,
+        };
 
         let disassemble_fn = bfd
-            .raw_disassembler(bfd.arch_mach.0, false, bfd.arch_mach.1)
+            .raw_disassembler(bfd.get_machine().unwrap(), false)
             .unwrap();
         let _ = di.configure_disassembler(disassemble_fn);
     }
+
+    #[test]
+    fn test_print_address_func() {
+        use libc::{c_ulong, uintptr_t};
+        use opcodes;
+        use opcodes::Endianness;
+        use utils;
+
+        extern "C" fn print_address(address: c_ulong, _info: *const uintptr_t) {
+            utils::opcode_buffer_append(&format!("<raw_{:x}>", address));
+        }
+
+        let bfd = match utils::test_bfd("i386:x86-64") {
+            Some(b) => b,
+            None => return,
+        };
+
+        // call 0x1005, the function must survive configure_buffer()
+        let buffer = [0xe8, 0x00, 0x00, 0x00, 0x00];
+        let mut di = opcodes::DisassembleInfo::new().unwrap();
+        di.set_print_address_func(print_address).unwrap();
+        di.init_buffer(&buffer, bfd, 0x1000, Endianness::Little)
+            .unwrap();
+        let call = di.disassemble().unwrap().unwrap();
+        assert_eq!(call.opcode, "call   <raw_1005>");
+
+        // A symbolizer replaces it
+        di.set_symbolizer(|address| format!("<target_{:x}>", address))
+            .unwrap();
+        di.set_range(0x1000, 0x1005).unwrap();
+        assert_eq!(
+            di.disassemble().unwrap().unwrap().opcode,
+            "call   <target_1005>"
+        );
+    }
+
+    #[test]
+    fn test_disassemble_errors() {
+        use opcodes;
+        use opcodes::Endianness;
+        use utils;
+        use Error;
+
+        let bfd = match utils::test_bfd("i386:x86-64") {
+            Some(b) => b,
+            None => return,
+        };
+
+        // A nop followed by a truncated call
+        let mut di = opcodes::DisassembleInfo::new().unwrap();
+        di.init_buffer(&[0x90, 0xe8, 0x00], bfd, 0x1000, Endianness::Little)
+            .unwrap();
+        assert_eq!(di.disassemble().unwrap().unwrap().opcode, "nop");
+        match di.disassemble() {
+            Some(Err(Error::TruncatedInstruction { vma })) => assert_eq!(vma, 0x1001),
+            _ => assert!(false),
+        };
+
+        // The buffer is exhausted
+        let mut di = opcodes::DisassembleInfo::new().unwrap();
+        di.init_buffer(&[0x90], bfd, 0x1000, Endianness::Little)
+            .unwrap();
+        assert!(di.disassemble().unwrap().is_ok());
+        assert!(di.disassemble().is_none());
+
+        // An invalid encoding
+        let mut di = opcodes::DisassembleInfo::new().unwrap();
+        di.init_buffer(&[0x06], bfd, 0x1000, Endianness::Little)
+            .unwrap();
+        match di.disassemble() {
+            Some(Err(Error::InvalidInstruction { vma })) => assert_eq!(vma, 0x1000),
+            _ => assert!(false),
+        };
+    }
+
+    #[test]
+    fn test_disassemble_range() {
+        use opcodes::Endianness;
+        use utils;
+        use Error;
+
+        // nop; call 0x1006; nop
+        let buffer = [0x90, 0xe8, 0x00, 0x00, 0x00, 0x00, 0x90];
+        let mut di = match utils::test_buffer("i386:x86-64", &buffer, 0x1000, Endianness::Little) {
+            Some(i) => i,
+            None => return,
+        };
+
+        // The call straddles the end of the range
+        let instructions: Vec<_> = di.disassemble_range(0x1000, 0x1003).collect();
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[0].opcode, "nop");
+        match instructions[1].error {
+            Some(Error::TruncatedInstruction { vma }) => assert_eq!(vma, 0x1001),
+            _ => assert!(false),
+        };
+
+        let instructions: Vec<_> = di.disassemble_range(0x1006, 0x1007).collect();
+        assert_eq!(instructions.len(), 1);
+        assert_eq!(instructions[0].offset, 0x1006);
+        assert!(instructions[0].error.is_none());
+
+        // Invalid ranges keep the previous one
+        assert!(di.set_range(0x1003, 0x1001).is_err());
+        assert!(di.set_range(0x2000, 0x2001).is_err());
+        assert_eq!(di.range, Some((0x1006, 0x1007)));
+    }
+
+    #[test]
+    fn test_configure_symbols() {
+        use bfd;
+        use opcodes;
+        use utils;
+
+        let mut di = opcodes::DisassembleInfo::new().unwrap();
+        assert!(di.configure_symbols(bfd::Bfd::empty()).is_err());
+
+        let bfd = match utils::open_test_binary() {
+            Some(b) => b,
+            None => return,
+        };
+        let section = bfd.get_section_by_name(".text").unwrap();
+
+        di.configure(section, bfd).unwrap();
+        assert!(di.symbol_table.is_some());
+        di.configure_disassembler(bfd.disassembler().unwrap())
+            .unwrap();
+        di.init().unwrap();
+        assert!(di.disassemble().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_configure_symbols_mapping() {
+        use opcodes;
+        use utils;
+
+        let disassemble = |name, target, arch_name| {
+            let bfd = utils::open_test_file(name, target, arch_name)?;
+            let section = bfd.get_section_by_name(".text").unwrap();
+
+            let mut di = opcodes::DisassembleInfo::new().unwrap();
+            di.configure(section, bfd).unwrap();
+            assert!(di.symbol_table.is_some());
+            di.configure_disassembler(bfd.disassembler().unwrap())
+                .unwrap();
+            di.init().unwrap();
+            let instructions: Vec<(u64, String)> = di
+                .disassemble_all()
+                .unwrap()
+                .into_iter()
+                .map(|i| (i.offset, i.opcode))
+                .collect();
+            Some(instructions)
+        };
+
+        // $a at 0x0, $d at 0xc and $t at 0x10
+        if let Some(instructions) = disassemble("arm.o", "elf32-littlearm", "arm") {
+            let opcodes: Vec<&str> = instructions.iter().map(|i| i.1.as_str()).collect();
+            assert_eq!(instructions.len(), 6);
+            assert!(opcodes[0].starts_with("ldr"));
+            assert_eq!(instructions[3].0, 0xc);
+            assert_eq!(opcodes[3], ".word\t0xdeadbeef");
+            assert_eq!(instructions[4].0, 0x10);
+            assert_eq!(opcodes[4], "movs\tr0, #1");
+            assert_eq!(opcodes[5], "bx\tlr");
+        }
+
+        // $x at 0x0 and $d at 0x8
+        if let Some(instructions) = disassemble("aarch64.o", "elf64-littleaarch64", "aarch64") {
+            let opcodes: Vec<&str> = instructions.iter().map(|i| i.1.as_str()).collect();
+            assert_eq!(opcodes.len(), 3);
+            assert_eq!(opcodes[1], "ret");
+            assert_eq!(instructions[2].0, 0x8);
+            assert_eq!(opcodes[2], ".word\t0xdeadbeef");
+        }
+    }
+
+    #[test]
+    fn test_disassemble_recovery() {
+        use opcodes;
+        use opcodes::Endianness;
+        use utils;
+
+        let bfd = match utils::test_bfd("i386:x86-64") {
+            Some(b) => b,
+            None => return,
+        };
+
+        // An invalid byte, a nop and a truncated call
+        let buffer = [0x06, 0x90, 0xe8, 0x00];
+        let mut di = opcodes::DisassembleInfo::new().unwrap();
+        di.init_buffer(&buffer, bfd, 0x1000, Endianness::Little)
+            .unwrap();
+        di.set_recovery(true);
+
+        let instructions: Vec<_> = di.disassemble_range(0x1000, 0x1004).collect();
+        let opcodes: Vec<_> = instructions.iter().map(|i| i.opcode.as_str()).collect();
+        assert_eq!(
+            opcodes,
+            vec![".byte 0x06", "nop", ".byte 0xe8", ".byte 0x00"]
+        );
+        assert!(instructions.iter().all(|i| i.error.is_none()));
+
+        // lea with a register operand is printed with a (bad) operand, and skipped as a whole
+        let buffer = [0x8d, 0xc0, 0x90];
+        let mut di = opcodes::DisassembleInfo::new().unwrap();
+        di.init_buffer(&buffer, bfd, 0x1000, Endianness::Little)
+            .unwrap();
+        di.set_recovery(true);
+
+        let instructions: Vec<_> = di.disassemble_range(0x1000, 0x1003).collect();
+        let opcodes: Vec<_> = instructions.iter().map(|i| i.opcode.as_str()).collect();
+        assert_eq!(opcodes, vec![".byte 0x8d, 0xc0", "nop"]);
+    }
+
+    #[test]
+    fn test_disassemble_recovery_mips16() {
+        use mips::MipsIsaMode;
+        use opcodes::Endianness;
+        use utils;
+
+        // nop; nop
+        let buffer = [0x65, 0x00, 0x65, 0x00];
+        let mut di = match utils::test_buffer("mips:isa32", &buffer, 0x1000, Endianness::Big) {
+            Some(i) => i,
+            None => return,
+        };
+        di.set_isa_modes(vec![(0x1000, MipsIsaMode::Mips16)]);
+        di.set_recovery(true);
+
+        // Undecodable MIPS16 instructions are skipped 2 bytes at a time
+        let instructions: Vec<_> = di.disassemble_range(0x1000, 0x1004).collect();
+        let lengths: Vec<_> = instructions.iter().map(|i| i.length).collect();
+        assert_eq!(lengths, vec![2, 2]);
+        assert_eq!(di.min_instruction_size(), 2);
+    }
+
+    #[test]
+    fn test_disassemble_all() {
+        use opcodes::Endianness;
+        use utils;
+
+        let buffer = [0x90, 0x06, 0xc3];
+        let mut di = match utils::test_buffer("i386:x86-64", &buffer, 0x1000, Endianness::Little) {
+            Some(i) => i,
+            None => return,
+        };
+
+        let instructions = di.disassemble_all().unwrap();
+        let opcodes: Vec<_> = instructions.iter().map(|i| i.opcode.as_str()).collect();
+        assert_eq!(opcodes, vec!["nop", ".byte 0x06", "ret"]);
+        assert!(di.disassemble().is_none());
+
+        let instructions =
+            utils::disassemble_all("i386:x86-64", &buffer, 0x1000, Endianness::Little).unwrap();
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(instructions[2].offset, 0x1002);
+    }
+
+    #[test]
+    fn test_disassemble_batch() {
+        use opcodes::Endianness;
+        use utils;
+
+        if !utils::has_arch("i386:x86-64") {
+            return;
+        }
+        // call 0x1007; .byte 0x06; nop; ret
+        let buffer = [0xe8, 0x02, 0x00, 0x00, 0x00, 0x06, 0x90, 0xc3];
+        let expected =
+            utils::disassemble_all("i386:x86-64", &buffer, 0x1000, Endianness::Little).unwrap();
+        let batch =
+            utils::disassemble_batch("i386:x86-64", &buffer, 0x1000, Endianness::Little).unwrap();
+
+        assert_eq!(batch.len(), expected.len());
+        for (i, e) in batch.iter().zip(expected.iter()) {
+            assert_eq!(
+                (i.offset, i.length, i.opcode),
+                (e.offset, e.length, e.opcode.as_str())
+            );
+            assert_eq!(i.bytes, e.bytes());
+            assert_eq!(i.addresses, e.addresses());
+            assert_eq!((i.insn_type, i.target), (e.insn_type, e.target));
+        }
+        assert_eq!(batch.get(1).unwrap().opcode, ".byte 0x06");
+        assert_eq!(batch.get(0).unwrap().target, Some(0x1007));
+    }
 }
@@ -0,0 +1,302 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - parallel.rs
+
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use arch::{Architecture, Machine};
+use batch::InstructionBatch;
+use bfd::Bfd;
+use instruction::Instruction;
+use opcodes::{DisassembleInfo, Endianness};
+use section::Section;
+use Error;
+
+// Bytes of a code section, shared by the worker threads
+struct CodeSection {
+    vma: u64,
+    data: Vec<u8>,
+}
+
+// Range disassembled by a single worker thread
+struct Chunk {
+    section: usize,
+    start: u64,
+    end: u64,
+}
+
+/// Disassemble all code sections with several threads, and return the instructions in address order
+///
+/// Sections are split at symbol boundaries, and each thread uses its own DisassembleInfo. As
+/// with Bfd::disassemble_all(), an instruction that contains a symbol is not split.
+/// Note: only x86 code is disassembled in parallel. Other disassemblers depend on mapping
+///       symbols, ISA modes or static variables, so they use a single thread like
+///       Bfd::disassemble_all().
+pub fn disassemble_parallel(
+    bfd: Bfd,
+    threads: usize,
+) -> Result<Vec<(Section, Instruction<'static>)>, Error> {
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let machine = bfd.get_machine()?;
+    if machine.architecture() != Architecture::I386 {
+        let mut disassembly = bfd.disassemble_all()?;
+        disassembly.set_recovery(true);
+        return Ok(disassembly.collect());
+    }
+    let endian = match bfd.is_big_endian()? {
+        true => Endianness::Big,
+        false => Endianness::Little,
+    };
+
+    let symbols = bfd.get_symbols().unwrap_or_default();
+
+    // Sections are disassembled in address order, like Bfd::disassemble_all()
+    let mut sections = Vec::new();
+    for section in bfd.get_sections()? {
+        if section.is_code()? && section.get_size()? > 0 {
+            sections.push((section.get_vma()?, section));
+        }
+    }
+    sections.sort_by_key(|s| s.0);
+    let sections: Vec<_> = sections.into_iter().map(|s| s.1).collect();
+
+    // Read the code sections from this thread, since libbfd is not thread safe
+    let mut code = Vec::new();
+    let mut boundaries = Vec::new();
+    for section in &sections {
+        let vma = section.get_vma()?;
+        let data = section.get_contents(bfd)?;
+        let end = vma + data.len() as u64;
+
+        let mut starts: Vec<_> = symbols
+            .iter()
+            .filter(|s| s.is_label() && s.section == Some(*section))
+            .map(|s| s.address)
+            .filter(|a| *a > vma && *a < end)
+            .collect();
+        starts.sort_unstable();
+        starts.dedup();
+        starts.insert(0, vma);
+        starts.push(end);
+
+        boundaries.push(starts);
+        code.push(CodeSection { vma, data });
+    }
+
+    // Group functions into chunks, so that threads get several chunks of similar sizes
+    let total: u64 = code.iter().map(|c| c.data.len() as u64).sum();
+    let chunk_size = (total / (threads as u64 * 8)).max(0x1000);
+    let mut chunks = Vec::new();
+    for (index, starts) in boundaries.iter().enumerate() {
+        let mut start = starts[0];
+        for address in &starts[1..] {
+            if address - start >= chunk_size || *address == *starts.last().unwrap() {
+                chunks.push(Chunk {
+                    section: index,
+                    start,
+                    end: *address,
+                });
+                start = *address;
+            }
+        }
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut decoded: Vec<Option<Result<InstructionBatch, Error>>> =
+        (0..chunks.len()).map(|_| None).collect();
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(chunks.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let chunk = match chunks.get(index) {
+                            Some(c) => c,
+                            None => break,
+                        };
+                        let code = &code[chunk.section];
+                        let result =
+                            disassemble_chunk(machine, endian, code, chunk.start, chunk.end);
+                        results.push((index, result));
+                    }
+                    results
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            let results = match worker.join() {
+                Ok(r) => r,
+                Err(e) => panic::resume_unwind(e),
+            };
+            for (index, result) in results {
+                decoded[index] = Some(result);
+            }
+        }
+    });
+
+    // Merge the chunks in address order
+    // Note: when the last instruction of a chunk ends after the next chunk start, the next
+    //       chunk starts at the end of this instruction. It is decoded again if none of its
+    //       instructions start there.
+    let mut instructions = Vec::with_capacity((total / 4) as usize);
+    let mut resume = None;
+    for (chunk, result) in chunks.iter().zip(decoded) {
+        let section = sections[chunk.section];
+        // Note: each chunk was stored by a worker thread
+        let mut result = match result {
+            Some(r) => r,
+            None => unreachable!(),
+        };
+
+        let start = match resume {
+            Some((index, vma)) if index == chunk.section && vma > chunk.start => vma,
+            _ => chunk.start,
+        };
+        if start >= chunk.end {
+            continue;
+        }
+        let synchronized = match result {
+            Ok(ref batch) => start == chunk.start || batch.iter().any(|i| i.offset == start),
+            Err(_) => true,
+        };
+        if !synchronized {
+            result = disassemble_chunk(machine, endian, &code[chunk.section], start, chunk.end);
+        }
+
+        match result {
+            Ok(batch) => {
+                for instruction in batch.iter().filter(|i| i.offset >= start) {
+                    resume = Some((chunk.section, instruction.offset + instruction.length));
+                    instructions.push((section, instruction.to_instruction()));
+                }
+            }
+            Err(e) => {
+                resume = None;
+                instructions.push((section, Instruction::empty_with_error(Some(e))));
+            }
+        }
+    }
+
+    Ok(instructions)
+}
+
+// Note: only owned data is returned to the main thread
+fn disassemble_chunk(
+    machine: Machine,
+    endian: Endianness,
+    code: &CodeSection,
+    start: u64,
+    end: u64,
+) -> Result<InstructionBatch, Error> {
+    let mut info = DisassembleInfo::new()?;
+    info.init_buffer(&code.data, Bfd::from_machine(machine), code.vma, endian)?;
+    // Note: the last instruction may end after the chunk, but not after the section
+    info.set_range(start, code.vma + code.data.len() as u64)?;
+    info.disassemble_batch_until(end)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_disassemble_parallel() {
+        use bfd;
+        use utils;
+
+        assert!(bfd::Bfd::empty().disassemble_parallel(4).is_err());
+
+        let bfd = match utils::open_test_binary() {
+            Some(b) => b,
+            None => return,
+        };
+
+        let mut disassembly = bfd.disassemble_all().unwrap();
+        disassembly.set_recovery(true);
+        let expected: Vec<_> = disassembly.map(|(_, i)| (i.offset, i.opcode)).collect();
+
+        for threads in &[1, 4] {
+            let instructions = bfd.disassemble_parallel(*threads).unwrap();
+            let offsets: Vec<_> = instructions
+                .into_iter()
+                .map(|(_, i)| (i.offset, i.opcode))
+                .collect();
+            assert_eq!(offsets, expected);
+        }
+    }
+
+    #[test]
+    fn test_disassemble_parallel_straddle() {
+        use utils;
+
+        let bfd = match utils::open_test_file("straddle.o", "elf64-x86-64", "i386:x86-64") {
+            Some(b) => b,
+            None => return,
+        };
+
+        let mut disassembly = bfd.disassemble_all().unwrap();
+        disassembly.set_recovery(true);
+        let expected: Vec<_> = disassembly.map(|(_, i)| (i.offset, i.opcode)).collect();
+        // The symbol at 0x1006 is inside the movabs instruction
+        assert!(expected
+            .iter()
+            .any(|i| i.0 == 0x1000 && i.1.starts_with("movabs")));
+        assert!(expected.iter().all(|i| i.0 != 0x1006));
+
+        for threads in &[1, 4] {
+            let instructions = bfd.disassemble_parallel(*threads).unwrap();
+            let offsets: Vec<_> = instructions
+                .into_iter()
+                .map(|(_, i)| (i.offset, i.opcode))
+                .collect();
+            assert_eq!(offsets, expected);
+        }
+    }
+
+    #[test]
+    fn test_send() {
+        use batch::InstructionBatch;
+        use Error;
+
+        // Worker threads only return owned data
+        fn is_send<T: Send>() {}
+        is_send::<Result<InstructionBatch, Error>>();
+    }
+
+    #[test]
+    fn test_threads() {
+        use opcodes::Endianness;
+        use std::thread;
+        use utils;
+
+        if !utils::has_arch("i386:x86-64") {
+            return;
+        }
+        // push rbp; mov rbp,rsp; nop; pop rbp; ret
+        let buffer = [0x55, 0x48, 0x89, 0xe5, 0x90, 0x5d, 0xc3];
+        let disassemble = || -> Vec<String> {
+            utils::disassemble_all("i386:x86-64", &buffer, 0x1000, Endianness::Little)
+                .unwrap()
+                .into_iter()
+                .map(|i| i.opcode)
+                .collect()
+        };
+        let expected = disassemble();
+        assert_eq!(expected.len(), 5);
+
+        // Each thread must build its own opcodes
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        assert_eq!(disassemble(), expected);
+                    }
+                });
+            }
+        });
+    }
+}
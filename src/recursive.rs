@@ -0,0 +1,193 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - recursive.rs
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use bfd::Bfd;
+use instruction::Instruction;
+use opcodes::DisassembleInfo;
+use section::Section;
+use symbol::{SymbolTable, BSF_FUNCTION};
+use utils;
+use Error;
+
+// A code section and its disassembler
+struct CodeSection {
+    start: u64,
+    end: u64,
+    info: DisassembleInfo,
+}
+
+/// Instructions reached by following the control flow from the entry points
+pub struct RecursiveDisassembly {
+    /// Reached instructions, indexed by address
+    pub instructions: BTreeMap<u64, Instruction<'static>>,
+    /// Ranges of code section bytes that were never reached, as (start, end)
+    pub unreached: Vec<(u64, u64)>,
+    /// Errors that stopped the traversal of a path
+    pub errors: Vec<Error>,
+}
+
+impl RecursiveDisassembly {
+    /// Follow the control flow from the start address and the function symbols
+    pub fn new(bfd: Bfd) -> Result<RecursiveDisassembly, Error> {
+        utils::check_null_pointer(bfd.raw(), "bfd pointer is null!")?;
+
+        let table = SymbolTable::sorted_from_bfd(bfd).ok().map(Rc::new);
+        let mut sections = Vec::new();
+        for section in bfd.get_sections()? {
+            if section.is_code()? && section.get_size()? > 0 {
+                sections.push(code_section(bfd, section, table.clone())?);
+            }
+        }
+        sections.sort_by_key(|s| s.start);
+
+        let mut entry_points = vec![bfd.get_start_address()?];
+        for symbol in bfd.get_symbols()? {
+            if symbol.is_label() && symbol.flags & BSF_FUNCTION != 0 {
+                entry_points.push(symbol.address);
+            }
+        }
+
+        RecursiveDisassembly::from_entry_points(sections, entry_points)
+    }
+
+    fn from_entry_points(
+        mut sections: Vec<CodeSection>,
+        mut pending: Vec<u64>,
+    ) -> Result<RecursiveDisassembly, Error> {
+        let mut instructions: BTreeMap<u64, Instruction<'static>> = BTreeMap::new();
+        let mut errors = Vec::new();
+
+        while let Some(mut pc) = pending.pop() {
+            let section = match sections.iter_mut().find(|s| s.start <= pc && pc < s.end) {
+                Some(s) => s,
+                None => continue,
+            };
+
+            // Disassemble linearly until the flow stops, or reaches known code
+            while pc < section.end && !instructions.contains_key(&pc) {
+                section.info.set_range(pc, section.end)?;
+                let instruction = match section.info.disassemble() {
+                    Some(Ok(i)) => i,
+                    Some(Err(e)) => {
+                        errors.push(e);
+                        break;
+                    }
+                    None => break,
+                };
+
+                if let Some(target) = instruction.target {
+                    pending.push(target);
+                }
+                let ends_flow = instruction
+                    .insn_type
+                    .map(|t| t.ends_flow())
+                    .unwrap_or(false);
+
+                pc += instruction.length;
+                instructions.insert(instruction.offset, instruction);
+                if ends_flow {
+                    break;
+                }
+            }
+        }
+
+        let unreached = unreached_ranges(&sections, &instructions);
+        Ok(RecursiveDisassembly {
+            instructions,
+            unreached,
+            errors,
+        })
+    }
+}
+
+fn code_section(
+    bfd: Bfd,
+    section: Section,
+    table: Option<Rc<SymbolTable>>,
+) -> Result<CodeSection, Error> {
+    let mut info = DisassembleInfo::new()?;
+    info.configure_with_symbols(section, bfd, table)?;
+    info.configure_disassembler(bfd.disassembler()?)?;
+    info.init()?;
+
+    let start = section.get_vma()?;
+    Ok(CodeSection {
+        start,
+        end: start + section.get_size()?,
+        info,
+    })
+}
+
+// Compute the gaps between reached instructions
+fn unreached_ranges(
+    sections: &[CodeSection],
+    instructions: &BTreeMap<u64, Instruction<'static>>,
+) -> Vec<(u64, u64)> {
+    let mut unreached = Vec::new();
+    for section in sections {
+        let mut current = section.start;
+        for (address, instruction) in instructions.range(section.start..section.end) {
+            if *address > current {
+                unreached.push((current, *address));
+            }
+            current = current.max(address + instruction.length);
+        }
+        if current < section.end {
+            unreached.push((current, section.end));
+        }
+    }
+    unreached
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_recursive_buffer() {
+        use opcodes::Endianness;
+        use recursive::{CodeSection, RecursiveDisassembly};
+        use utils;
+
+        // call 0x100a; jmp 0x100b; .byte 0xff, 0xff; nop; ret; nop
+        let buffer = [
+            0xe8, 0x05, 0x00, 0x00, 0x00, 0xeb, 0x04, 0xff, 0xff, 0x90, 0xc3, 0x90,
+        ];
+        let info = match utils::test_buffer("i386:x86-64", &buffer, 0x1000, Endianness::Little) {
+            Some(i) => i,
+            None => return,
+        };
+        let section = CodeSection {
+            start: 0x1000,
+            end: 0x1000 + buffer.len() as u64,
+            info,
+        };
+
+        let disassembly =
+            RecursiveDisassembly::from_entry_points(vec![section], vec![0x1000]).unwrap();
+        let addresses: Vec<_> = disassembly.instructions.keys().cloned().collect();
+        assert_eq!(addresses, vec![0x1000, 0x1005, 0x100a, 0x100b]);
+        assert_eq!(disassembly.instructions[&0x1000].target, Some(0x100a));
+        assert_eq!(disassembly.unreached, vec![(0x1007, 0x100a)]);
+        assert!(disassembly.errors.is_empty());
+    }
+
+    #[test]
+    fn test_recursive_bfd() {
+        use utils;
+
+        let bfd = match utils::open_test_binary() {
+            Some(b) => b,
+            None => return,
+        };
+
+        let disassembly = bfd.disassemble_recursive().unwrap();
+        let start = bfd.get_start_address().unwrap();
+        assert!(disassembly.instructions.contains_key(&start));
+
+        // square() is only reached from the call in sum_squares()
+        assert!(disassembly.instructions.contains_key(&0x1139));
+        assert!(disassembly.instructions.contains_key(&0x1168));
+    }
+}
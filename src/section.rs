@@ -1,20 +1,47 @@
 // Guillaume Valadon <guillaume@valadon.net>
 // binutils - section.rs
 
-use libc::c_ulong;
+use libc::{c_char, c_uint, c_ulong, c_void};
 
+use std::ffi::CStr;
 use std::ptr;
 
+use bfd::{Bfd, BfdRaw};
 use utils;
 use Error;
 
 extern "C" {
+    fn bfd_get_section_contents(
+        bfd: *const BfdRaw,
+        section: *const SectionRaw,
+        location: *mut c_void,
+        offset: i64,
+        count: u64,
+    ) -> bool;
+
     fn get_section_size(section: *const SectionRaw) -> c_ulong;
+
+    fn get_section_vma(section: *const SectionRaw) -> c_ulong;
+
+    fn get_section_flags(section: *const SectionRaw) -> c_uint;
+
+    fn get_section_name(section: *const SectionRaw) -> *const c_char;
+
+    fn get_next_section(section: *const SectionRaw) -> *const SectionRaw;
 }
 
+// Section flags, from bfd.h
+pub const SEC_ALLOC: u32 = 0x1;
+pub const SEC_LOAD: u32 = 0x2;
+pub const SEC_RELOC: u32 = 0x4;
+pub const SEC_READONLY: u32 = 0x8;
+pub const SEC_CODE: u32 = 0x10;
+pub const SEC_DATA: u32 = 0x20;
+pub const SEC_HAS_CONTENTS: u32 = 0x100;
+
 pub enum SectionRaw {}
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Section {
     pub section: *const SectionRaw,
 }
@@ -44,4 +71,54 @@ impl Section {
 
         Ok(unsafe { get_section_size(self.section) })
     }
+
+    pub fn get_vma(self) -> Result<c_ulong, Error> {
+        utils::check_null_pointer(self.section, "section pointer is null!")?;
+
+        Ok(unsafe { get_section_vma(self.section) })
+    }
+
+    pub fn get_flags(self) -> Result<u32, Error> {
+        utils::check_null_pointer(self.section, "section pointer is null!")?;
+
+        Ok(unsafe { get_section_flags(self.section) })
+    }
+
+    pub(crate) fn next(self) -> Option<Section> {
+        if self.section.is_null() {
+            return None;
+        }
+
+        Section::from_raw(unsafe { get_next_section(self.section) }).ok()
+    }
+
+    pub fn is_code(self) -> Result<bool, Error> {
+        let flags = self.get_flags()?;
+        Ok(flags & SEC_CODE != 0 && flags & SEC_HAS_CONTENTS != 0)
+    }
+
+    /// Read the content of the section
+    pub fn get_contents(self, bfd: Bfd) -> Result<Vec<u8>, Error> {
+        utils::check_null_pointer(self.section, "section pointer is null!")?;
+        utils::check_null_pointer(bfd.raw(), "bfd pointer is null!")?;
+
+        let size = self.get_size()?;
+        let mut data = vec![0; size as usize];
+        let location = data.as_mut_ptr() as *mut c_void;
+        if !unsafe { bfd_get_section_contents(bfd.raw(), self.section, location, 0, size) } {
+            return Err(Error::MemoryError {
+                vma: self.get_vma()?,
+            });
+        }
+        Ok(data)
+    }
+
+    pub fn get_name(self) -> Result<String, Error> {
+        utils::check_null_pointer(self.section, "section pointer is null!")?;
+
+        let name = unsafe { get_section_name(self.section) };
+        utils::check_null_pointer(name, "section name pointer is null!")?;
+
+        Ok(unsafe { CStr::from_ptr(name) }.to_str()?.to_string())
+    }
 }
@@ -0,0 +1,214 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - source.rs
+
+use libc::{c_char, c_uint, c_ulong};
+
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bfd::{Bfd, BfdRaw};
+use section::{Section, SectionRaw};
+use symbol::{SymbolRaw, SymbolTable};
+use utils;
+use Error;
+
+extern "C" {
+    fn find_nearest_line(
+        bfd: *const BfdRaw,
+        section: *const SectionRaw,
+        symbols: *mut *const SymbolRaw,
+        vma: c_ulong,
+        filename: *mut *const c_char,
+        function: *mut *const c_char,
+        line: *mut c_uint,
+    ) -> bool;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub function: Option<String>,
+    pub line: u32,
+}
+
+/// Maps addresses to source lines using the debug information of a bfd
+pub struct SourceLines {
+    bfd: Bfd,
+    symbols: SymbolTable,
+}
+
+impl SourceLines {
+    pub fn new(bfd: Bfd) -> Result<SourceLines, Error> {
+        utils::check_null_pointer(bfd.raw(), "bfd pointer is null!")?;
+
+        Ok(SourceLines {
+            bfd,
+            symbols: SymbolTable::from_bfd(bfd)?,
+        })
+    }
+
+    /// Return the source line that generated the instruction located at vma
+    pub fn find(&self, section: Section, vma: u64) -> Option<SourceLocation> {
+        let mut filename = std::ptr::null();
+        let mut function = std::ptr::null();
+        let mut line = 0;
+        let found = unsafe {
+            find_nearest_line(
+                self.bfd.raw(),
+                section.raw(),
+                self.symbols.raw(),
+                vma,
+                &mut filename,
+                &mut function,
+                &mut line,
+            )
+        };
+        if !found || filename.is_null() || line == 0 {
+            return None;
+        }
+
+        let to_string = |s: *const c_char| unsafe { CStr::from_ptr(s) }.to_string_lossy();
+        Some(SourceLocation {
+            file: to_string(filename).into_owned(),
+            function: match function.is_null() {
+                true => None,
+                false => Some(to_string(function).into_owned()),
+            },
+            line,
+        })
+    }
+}
+
+/// Reads source files from their recorded paths, remapped prefixes or search paths
+#[derive(Default)]
+pub struct SourceFiles {
+    search_paths: Vec<PathBuf>,
+    prefix_map: Vec<(String, String)>,
+    // Note: None if the file could not be read
+    cache: HashMap<String, Option<Vec<String>>>,
+}
+
+impl SourceFiles {
+    pub fn new() -> SourceFiles {
+        SourceFiles::default()
+    }
+
+    /// Look for source files in this directory, using their relative path then their name
+    pub fn add_search_path<P: AsRef<Path>>(&mut self, path: P) {
+        self.search_paths.push(path.as_ref().to_path_buf());
+    }
+
+    /// Replace a path prefix, i.e. the build directory, like `--prefix-map`
+    pub fn add_prefix_map(&mut self, from: &str, to: &str) {
+        self.prefix_map.push((from.to_string(), to.to_string()));
+    }
+
+    fn candidates(&self, file: &str) -> Vec<PathBuf> {
+        let mut file = file.to_string();
+        for (from, to) in &self.prefix_map {
+            if file.starts_with(from.as_str()) {
+                file = format!("{}{}", to, &file[from.len()..]);
+                break;
+            }
+        }
+
+        let path = Path::new(&file);
+        let mut candidates = vec![path.to_path_buf()];
+        let relative = path.strip_prefix("/").unwrap_or(path);
+        for directory in &self.search_paths {
+            candidates.push(directory.join(relative));
+            if let Some(name) = path.file_name() {
+                candidates.push(directory.join(name));
+            }
+        }
+        candidates
+    }
+
+    /// Return a source line, numbered from 1
+    pub fn line(&mut self, file: &str, line: u32) -> Option<&str> {
+        if !self.cache.contains_key(file) {
+            let lines = self
+                .candidates(file)
+                .iter()
+                .filter_map(|p| fs::read(p).ok())
+                .next()
+                .map(|data| {
+                    String::from_utf8_lossy(&data)
+                        .lines()
+                        .map(|l| l.to_string())
+                        .collect()
+                });
+            self.cache.insert(file.to_string(), lines);
+        }
+
+        let lines = self.cache.get(file)?.as_ref()?;
+        lines
+            .get((line as usize).checked_sub(1)?)
+            .map(|l| l.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_source_files() {
+        use source::SourceFiles;
+        use std::fs;
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        // Note: tests run in parallel, and possibly in several processes
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .subsec_nanos();
+        let directory = std::env::temp_dir().join(format!(
+            "binutils-rs-source-{}-{}",
+            std::process::id(),
+            nanos
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("main.c"), "int main() {\n  return 0;\n}\n").unwrap();
+
+        let mut files = SourceFiles::new();
+        files.add_prefix_map("/build", directory.to_str().unwrap());
+        assert_eq!(files.line("/build/main.c", 2), Some("  return 0;"));
+        assert_eq!(files.line("/build/main.c", 4), None);
+
+        let mut files = SourceFiles::new();
+        files.add_search_path(&directory);
+        assert_eq!(files.line("/elsewhere/main.c", 1), Some("int main() {"));
+        assert_eq!(files.line("/elsewhere/missing.c", 1), None);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_source_lines() {
+        use source::{SourceFiles, SourceLines};
+        use utils;
+
+        let bfd = match utils::open_test_binary() {
+            Some(b) => b,
+            None => return,
+        };
+        let section = bfd.get_section_by_name(".text").unwrap();
+
+        // imul eax,eax in square()
+        let lines = SourceLines::new(bfd).unwrap();
+        let location = lines.find(section, 0x1143).unwrap();
+        assert!(location.file.ends_with("hello.c"));
+        assert_eq!(location.line, 8);
+        assert_eq!(location.function, Some("square".to_string()));
+        assert!(lines.find(section, 0).is_none());
+
+        // The binary records hello.c relatively to resources/tests
+        let mut files = SourceFiles::new();
+        files.add_search_path(concat!(env!("CARGO_MANIFEST_DIR"), "/resources/tests"));
+        assert_eq!(
+            files.line(&location.file, location.line),
+            Some("    return value * value;")
+        );
+    }
+}
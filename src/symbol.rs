@@ -0,0 +1,199 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils libbfd symbols - symbol.rs
+
+use libc::{c_char, c_long, c_uint, c_ulong};
+
+use std;
+use std::ffi::CStr;
+
+use bfd::{Bfd, BfdRaw};
+use section::{Section, SectionRaw};
+use utils;
+use Error;
+
+extern "C" {
+    fn get_symbol_table(bfd: *const BfdRaw, count: *mut c_long) -> *mut *const SymbolRaw;
+
+    fn get_dynamic_symbol_table(bfd: *const BfdRaw, count: *mut c_long) -> *mut *const SymbolRaw;
+
+    fn sort_symbol_table(symbols: *mut *const SymbolRaw, count: c_long);
+
+    fn get_symbol_name(symbol: *const SymbolRaw) -> *const c_char;
+
+    fn get_symbol_address(symbol: *const SymbolRaw) -> c_ulong;
+
+    fn get_symbol_flags(symbol: *const SymbolRaw) -> c_uint;
+
+    fn is_symbol_defined(symbol: *const SymbolRaw) -> bool;
+
+    fn get_symbol_section(symbol: *const SymbolRaw) -> *const SectionRaw;
+
+    fn get_symbol_size(symbol: *const SymbolRaw) -> c_ulong;
+
+    fn get_symbol_other(symbol: *const SymbolRaw) -> u8;
+}
+
+// Symbol flags, from bfd.h
+pub const BSF_LOCAL: u32 = 1 << 0;
+pub const BSF_GLOBAL: u32 = 1 << 1;
+pub const BSF_DEBUGGING: u32 = 1 << 3;
+pub const BSF_FUNCTION: u32 = 1 << 4;
+pub const BSF_WEAK: u32 = 1 << 7;
+pub const BSF_SECTION_SYM: u32 = 1 << 8;
+pub const BSF_FILE: u32 = 1 << 14;
+pub const BSF_DYNAMIC: u32 = 1 << 15;
+pub const BSF_OBJECT: u32 = 1 << 16;
+pub const BSF_SYNTHETIC: u32 = 1 << 21;
+
+pub(crate) enum SymbolRaw {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u64,
+    pub flags: u32,
+    pub defined: bool,
+    // Note: 0 if the size is unknown
+    pub size: u64,
+    pub section: Option<Section>,
+    // ELF st_other, 0 for other formats
+    pub other: u8,
+}
+
+impl Symbol {
+    fn from_raw(symbol: *const SymbolRaw) -> Result<Symbol, Error> {
+        utils::check_null_pointer(symbol, "symbol pointer is null!")?;
+
+        let name = unsafe { get_symbol_name(symbol) };
+        utils::check_null_pointer(name, "symbol name pointer is null!")?;
+
+        Ok(Symbol {
+            name: unsafe { CStr::from_ptr(name) }.to_str()?.to_string(),
+            address: unsafe { get_symbol_address(symbol) },
+            flags: unsafe { get_symbol_flags(symbol) },
+            defined: unsafe { is_symbol_defined(symbol) },
+            size: unsafe { get_symbol_size(symbol) },
+            section: Section::from_raw(unsafe { get_symbol_section(symbol) }).ok(),
+            other: unsafe { get_symbol_other(symbol) },
+        })
+    }
+
+    /// Return true if the symbol names code or data, as opposed to files, sections or debug info
+    pub fn is_label(&self) -> bool {
+        self.defined
+            && !self.name.is_empty()
+            && self.flags & (BSF_DEBUGGING | BSF_SECTION_SYM | BSF_FILE) == 0
+    }
+}
+
+/// Symbols read from a bfd, as allocated by libbfd
+pub struct SymbolTable {
+    symbols: *mut *const SymbolRaw,
+    count: usize,
+}
+
+impl SymbolTable {
+    pub fn from_bfd(bfd: Bfd) -> Result<SymbolTable, Error> {
+        SymbolTable::read(bfd, get_symbol_table)
+    }
+
+    /// Read the symbols sorted by address, as expected by libopcodes
+    pub fn sorted_from_bfd(bfd: Bfd) -> Result<SymbolTable, Error> {
+        let mut table = SymbolTable::from_bfd(bfd)?;
+        table.sort();
+        Ok(table)
+    }
+
+    /// Read the dynamic symbols, which are kept in stripped binaries
+    pub fn dynamic_from_bfd(bfd: Bfd) -> Result<SymbolTable, Error> {
+        SymbolTable::read(bfd, get_dynamic_symbol_table)
+    }
+
+    fn read(
+        bfd: Bfd,
+        read_table: unsafe extern "C" fn(*const BfdRaw, *mut c_long) -> *mut *const SymbolRaw,
+    ) -> Result<SymbolTable, Error> {
+        utils::check_null_pointer(bfd.raw(), "bfd pointer is null!")?;
+
+        let mut count = 0;
+        let symbols = unsafe { read_table(bfd.raw(), &mut count) };
+        if count < 0 {
+            return Err(Error::BfdError(
+                0,
+                "Can't read the symbol table!".to_string(),
+            ));
+        }
+
+        Ok(SymbolTable {
+            symbols,
+            count: count as usize,
+        })
+    }
+
+    pub(crate) fn raw(&self) -> *mut *const SymbolRaw {
+        self.symbols
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Sort the symbols by address, as expected by libopcodes
+    pub fn sort(&mut self) {
+        unsafe { sort_symbol_table(self.symbols, self.count as c_long) };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn symbols(&self) -> Result<Vec<Symbol>, Error> {
+        if self.symbols.is_null() {
+            return Ok(Vec::new());
+        }
+
+        let raw_symbols = unsafe { std::slice::from_raw_parts(self.symbols, self.count) };
+        raw_symbols.iter().map(|s| Symbol::from_raw(*s)).collect()
+    }
+}
+
+impl Drop for SymbolTable {
+    fn drop(&mut self) {
+        if !self.symbols.is_null() {
+            unsafe { libc::free(self.symbols as *mut libc::c_void) };
+            self.symbols = std::ptr::null_mut();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_symbol_table_empty() {
+        use bfd;
+        use symbol;
+
+        assert!(symbol::SymbolTable::from_bfd(bfd::Bfd::empty()).is_err());
+    }
+
+    #[test]
+    fn test_symbol_table() {
+        use symbol;
+        use utils;
+
+        let bfd = match utils::open_test_binary() {
+            Some(b) => b,
+            None => return,
+        };
+
+        let table = symbol::SymbolTable::from_bfd(bfd).unwrap();
+        let symbols = table.symbols().unwrap();
+        assert_eq!(symbols.len(), table.len());
+        assert!(symbols.iter().all(|s| !s.is_label() || s.defined));
+
+        let square = symbols.iter().find(|s| s.name == "square").unwrap();
+        assert_eq!(square.address, 0x1139);
+        assert_eq!(square.size, 0xf);
+        assert_ne!(square.flags & symbol::BSF_FUNCTION, 0);
+    }
+}
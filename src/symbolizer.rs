@@ -0,0 +1,143 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - symbolizer.rs
+
+use libc::{c_ulong, uintptr_t};
+
+use bfd::Bfd;
+use helpers;
+use opcodes::{Callbacks, DisassembleInfoRaw};
+use symbol::Symbol;
+use utils;
+use Error;
+
+extern "C" {
+    fn generic_print_address(address: c_ulong, info: *const DisassembleInfoRaw);
+}
+
+/// Formats the addresses used by instructions, such as branch targets
+pub trait Symbolizer {
+    fn symbolize(&mut self, address: u64) -> String;
+}
+
+// Any closure can be used as a symbolizer
+impl<F> Symbolizer for F
+where
+    F: FnMut(u64) -> String,
+{
+    fn symbolize(&mut self, address: u64) -> String {
+        self(address)
+    }
+}
+
+/// Prints addresses like objdump, i.e. `0x401136 <main+0x16>`
+pub struct BfdSymbolizer {
+    // Sorted by address
+    symbols: Vec<(u64, String)>,
+}
+
+impl BfdSymbolizer {
+    pub fn new(bfd: Bfd) -> Result<BfdSymbolizer, Error> {
+        Ok(BfdSymbolizer::from_symbols(bfd.get_symbols()?))
+    }
+
+    pub fn from_symbols(symbols: Vec<Symbol>) -> BfdSymbolizer {
+        let mut symbols: Vec<(u64, String)> = symbols
+            .into_iter()
+            .filter(|s| s.is_label())
+            .map(|s| (s.address, s.name))
+            .collect();
+        symbols.sort();
+        symbols.dedup_by_key(|s| s.0);
+
+        BfdSymbolizer { symbols }
+    }
+
+    /// Return the closest symbol located before an address, and the offset from it
+    pub fn lookup(&self, address: u64) -> Option<(&str, u64)> {
+        let index = match self.symbols.binary_search_by_key(&address, |s| s.0) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+
+        let (symbol_address, ref name) = self.symbols[index];
+        Some((name, address - symbol_address))
+    }
+}
+
+impl Symbolizer for BfdSymbolizer {
+    fn symbolize(&mut self, address: u64) -> String {
+        match self.lookup(address) {
+            Some((name, 0)) => format!("0x{:x} <{}>", address, name),
+            Some((name, offset)) => format!("0x{:x} <{}+0x{:x}>", address, name, offset),
+            None => format!("0x{:x}", address),
+        }
+    }
+}
+
+// Called by libopcodes instead of its default generic_print_address()
+pub(crate) extern "C" fn print_address(address: c_ulong, info: *const uintptr_t) {
+    let callbacks = unsafe {
+        helpers::get_disassemble_info_application_data(info as *const DisassembleInfoRaw)
+    } as *mut Callbacks;
+
+    let callbacks = match unsafe { callbacks.as_mut() } {
+        Some(c) => c,
+        None => return,
+    };
+
+    // Keep track of the addresses used by the instruction, such as branch targets
+    callbacks.addresses.push(address);
+
+    match callbacks.symbolizer.as_mut() {
+        Some(symbolizer) => utils::opcode_buffer_append(&symbolizer.symbolize(address)),
+        None => unsafe { generic_print_address(address, info as *const DisassembleInfoRaw) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_bfd_symbolizer() {
+        use symbol::{Symbol, BSF_FILE, BSF_FUNCTION, BSF_GLOBAL};
+        use symbolizer::{BfdSymbolizer, Symbolizer};
+
+        let symbol = |name: &str, address: u64, flags: u32| Symbol {
+            name: name.to_string(),
+            address,
+            flags,
+            defined: true,
+            size: 0,
+            section: None,
+            other: 0,
+        };
+        let mut symbolizer = BfdSymbolizer::from_symbols(vec![
+            symbol("main", 0x401120, BSF_GLOBAL | BSF_FUNCTION),
+            symbol("crt1.c", 0x401000, BSF_FILE),
+            symbol("_start", 0x401000, BSF_GLOBAL | BSF_FUNCTION),
+        ]);
+
+        assert_eq!(symbolizer.symbolize(0x401136), "0x401136 <main+0x16>");
+        assert_eq!(symbolizer.symbolize(0x401000), "0x401000 <_start>");
+        assert_eq!(symbolizer.symbolize(0x400000), "0x400000");
+    }
+
+    #[test]
+    fn test_closure_symbolizer() {
+        use opcodes::Endianness;
+        use utils;
+
+        // call 0x1005
+        let buffer = [0xe8, 0x00, 0x00, 0x00, 0x00];
+        let mut di = match utils::test_buffer("i386:x86-64", &buffer, 0x1000, Endianness::Little) {
+            Some(i) => i,
+            None => return,
+        };
+        di.set_symbolizer(|address| format!("<target_{:x}>", address))
+            .unwrap();
+        assert_eq!(
+            di.disassemble().unwrap().unwrap().opcode,
+            "call   <target_1005>"
+        );
+    }
+}
@@ -1,16 +1,15 @@
 // Guillaume Valadon <guillaume@valadon.net>
 // binutils - utils.rs
 
+use batch::InstructionBatch;
 use bfd::{arch_list, Bfd};
 use helpers;
-use opcodes::DisassembleInfo;
+use instruction::Instruction;
+use memory::MemorySource;
+use opcodes::{DisassembleInfo, Endianness};
 use Error;
 
-pub fn disassemble_buffer(
-    arch_name: &str,
-    buffer: &[u8],
-    offset: u64,
-) -> Result<DisassembleInfo, Error> {
+fn bfd_from_arch_name(arch_name: &str) -> Result<Bfd, Error> {
     // Create a bfd structure
     let mut bfd = Bfd::empty();
 
@@ -21,13 +20,61 @@ pub fn disassemble_buffer(
     }
 
     // Set bfd_arch and bfd_mach from the architecture name
-    let _ = bfd.set_arch_mach(arch_name);
+    bfd.set_arch_mach(arch_name)?;
+
+    Ok(bfd)
+}
+
+pub fn disassemble_buffer(
+    arch_name: &str,
+    buffer: &[u8],
+    offset: u64,
+    endian: Endianness,
+) -> Result<DisassembleInfo, Error> {
+    let bfd = bfd_from_arch_name(arch_name)?;
 
     // Create a disassemble_info structure
     let mut info = DisassembleInfo::new()?;
 
     // Configure the disassemble_info structure
-    info.init_buffer(buffer, bfd, offset)?;
+    info.init_buffer(buffer, bfd, offset, endian)?;
+
+    Ok(info)
+}
+
+/// Disassemble a whole buffer at once, see DisassembleInfo::disassemble_all()
+pub fn disassemble_all(
+    arch_name: &str,
+    buffer: &[u8],
+    offset: u64,
+    endian: Endianness,
+) -> Result<Vec<Instruction<'static>>, Error> {
+    let mut info = disassemble_buffer(arch_name, buffer, offset, endian)?;
+    info.disassemble_all()
+}
+
+/// Disassemble a whole buffer into shared buffers, see DisassembleInfo::disassemble_batch()
+pub fn disassemble_batch(
+    arch_name: &str,
+    buffer: &[u8],
+    offset: u64,
+    endian: Endianness,
+) -> Result<InstructionBatch, Error> {
+    let mut info = disassemble_buffer(arch_name, buffer, offset, endian)?;
+    info.disassemble_batch()
+}
+
+pub fn disassemble_memory<M: MemorySource + 'static>(
+    arch_name: &str,
+    source: M,
+    vma: u64,
+    endian: Endianness,
+) -> Result<DisassembleInfo, Error> {
+    let bfd = bfd_from_arch_name(arch_name)?;
+
+    // Create and configure a disassemble_info structure
+    let mut info = DisassembleInfo::new()?;
+    info.init_memory_source(source, bfd, vma, endian)?;
 
     Ok(info)
 }
@@ -41,29 +88,99 @@ pub(crate) fn check_null_pointer<T>(pointer: *const T, message: &str) -> Result<
 }
 
 pub fn opcode_buffer_append(string: &str) {
-    unsafe {
-        let tmp = match helpers::CURRENT_OPCODE {
-            Some(ref o) => o,
-            None => "",
-        };
-        helpers::CURRENT_OPCODE = Some(format!("{}{}", tmp, string));
+    helpers::CURRENT_OPCODE.with(|opcode| opcode.borrow_mut().push_str(string));
+}
+
+/// Targets given to binutils configure, i.e. "all"
+#[cfg(not(feature = "system"))]
+pub fn targets() -> Vec<String> {
+    env!("BINUTILS_TARGETS")
+        .split(',')
+        .map(|t| t.to_string())
+        .collect()
+}
+
+/// Targets of the installed libbfd, i.e. "elf64-x86-64"
+#[cfg(feature = "system")]
+pub fn targets() -> Vec<String> {
+    ::bfd::target_list()
+}
+
+/// Return true if libbfd was built with this architecture, i.e. "i386:x86-64"
+// Note: cargo features can leave architectures out of the build
+#[cfg(test)]
+pub(crate) fn has_arch(arch_name: &str) -> bool {
+    arch_list().iter().any(|arch| arch == arch_name)
+}
+
+/// Return a Bfd set to this architecture, or None if libbfd was built without it
+#[cfg(test)]
+pub(crate) fn test_bfd(arch_name: &str) -> Option<Bfd> {
+    if !has_arch(arch_name) {
+        return None;
+    }
+    Some(bfd_from_arch_name(arch_name).expect("Can't set the architecture!"))
+}
+
+/// Prepare the disassembly of a buffer, or return None if libbfd was built without this
+/// architecture
+#[cfg(test)]
+pub(crate) fn test_buffer(
+    arch_name: &str,
+    buffer: &[u8],
+    vma: u64,
+    endian: Endianness,
+) -> Option<DisassembleInfo> {
+    if !has_arch(arch_name) {
+        return None;
     }
+    Some(disassemble_buffer(arch_name, buffer, vma, endian).expect("Can't configure the buffer!"))
+}
+
+/// Open the x86-64 test binary built from resources/tests/hello.c
+// Note: None only if the x86-64 target is not built
+#[cfg(test)]
+pub(crate) fn open_test_binary() -> Option<Bfd> {
+    open_test_file("hello", "elf64-x86-64", "i386:x86-64")
+}
+
+/// Open a file from resources/tests, or return None if libbfd was built without this
+/// architecture
+#[cfg(test)]
+pub(crate) fn open_test_file(name: &str, target: &str, arch_name: &str) -> Option<Bfd> {
+    use bfd::BfdFormat;
+
+    if !has_arch(arch_name) {
+        return None;
+    }
+
+    let filename = format!("{}/resources/tests/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let bfd = Bfd::openr(&filename, target).expect("Can't open the test binary!");
+    bfd.check_format(BfdFormat::bfd_object)
+        .expect("The test binary is not an object!");
+    Some(bfd)
 }
 
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn test_targets() {
+        use utils;
+
+        let targets = utils::targets();
+        assert!(!targets.is_empty());
+        assert!(targets.iter().all(|t| !t.is_empty()));
+    }
+
     #[test]
     fn test_opcode_buffer_append() {
-        use helpers;
+        use instruction;
         use utils;
 
-        unsafe {
-            helpers::CURRENT_OPCODE = None;
-            utils::opcode_buffer_append("te");
-            assert_eq!(helpers::CURRENT_OPCODE, Some("te".to_string()));
-            utils::opcode_buffer_append("st!");
-            assert_eq!(helpers::CURRENT_OPCODE, Some("test!".to_string()));
-            helpers::CURRENT_OPCODE = None;
-        }
+        assert!(instruction::get_opcode().is_err());
+        utils::opcode_buffer_append("te");
+        utils::opcode_buffer_append("st!");
+        assert_eq!(instruction::get_opcode().unwrap(), "test!");
+        assert!(instruction::get_opcode().is_err());
     }
 }
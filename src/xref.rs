@@ -0,0 +1,166 @@
+// Guillaume Valadon <guillaume@valadon.net>
+// binutils - xref.rs
+
+use std::collections::BTreeMap;
+
+use bfd::Bfd;
+use instruction::{Instruction, InstructionType};
+use Error;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum XrefKind {
+    Call,
+    Jump,
+    Data,
+}
+
+/// A reference from an instruction to an address
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Xref {
+    pub from: u64,
+    pub to: u64,
+    pub kind: XrefKind,
+}
+
+/// Code and data references, indexed by source and by target
+#[derive(Default)]
+pub struct XrefDatabase {
+    from: BTreeMap<u64, Vec<Xref>>,
+    to: BTreeMap<u64, Vec<Xref>>,
+}
+
+impl XrefDatabase {
+    /// Disassemble all code sections and record their references
+    pub fn new(bfd: Bfd) -> Result<XrefDatabase, Error> {
+        let mut database = XrefDatabase::default();
+        for (_, instruction) in bfd.disassemble_all()? {
+            database.add_instruction(&instruction);
+        }
+        Ok(database)
+    }
+
+    pub fn add_instruction(&mut self, instruction: &Instruction) {
+        if instruction.error.is_some() {
+            return;
+        }
+
+        let kind = match instruction.insn_type {
+            Some(InstructionType::Call) | Some(InstructionType::ConditionalCall) => XrefKind::Call,
+            Some(InstructionType::Branch) | Some(InstructionType::ConditionalBranch) => {
+                XrefKind::Jump
+            }
+            _ => XrefKind::Data,
+        };
+        if let Some(target) = instruction.target {
+            self.add(Xref {
+                from: instruction.offset,
+                to: target,
+                kind,
+            });
+        }
+
+        // Other printed addresses are data, i.e. RIP-relative operands
+        for address in instruction.addresses() {
+            if Some(*address) != instruction.target {
+                self.add(Xref {
+                    from: instruction.offset,
+                    to: *address,
+                    kind: XrefKind::Data,
+                });
+            }
+        }
+    }
+
+    pub fn add(&mut self, xref: Xref) {
+        let references = self.from.entry(xref.from).or_default();
+        if references.contains(&xref) {
+            return;
+        }
+        references.push(xref);
+        self.to.entry(xref.to).or_default().push(xref);
+    }
+
+    /// Return the references made by the instruction located at this address
+    pub fn references_from(&self, address: u64) -> &[Xref] {
+        self.from.get(&address).map(|r| &r[..]).unwrap_or(&[])
+    }
+
+    /// Return the references to this address
+    pub fn references_to(&self, address: u64) -> &[Xref] {
+        self.to.get(&address).map(|r| &r[..]).unwrap_or(&[])
+    }
+
+    /// Return the addresses of the instructions that call this address
+    pub fn callers(&self, address: u64) -> Vec<u64> {
+        self.references_to(address)
+            .iter()
+            .filter(|x| x.kind == XrefKind::Call)
+            .map(|x| x.from)
+            .collect()
+    }
+
+    /// Return the addresses of the instructions that use this data address
+    pub fn readers(&self, address: u64) -> Vec<u64> {
+        self.references_to(address)
+            .iter()
+            .filter(|x| x.kind == XrefKind::Data)
+            .map(|x| x.from)
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.from.values().map(|r| r.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.from.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_xref_buffer() {
+        use opcodes::Endianness;
+        use utils;
+        use xref::{XrefDatabase, XrefKind};
+
+        // call 0x100c; mov eax,DWORD PTR [rip+0x10]; ret; ret
+        let buffer = [
+            0xe8, 0x07, 0x00, 0x00, 0x00, 0x8b, 0x05, 0x10, 0x00, 0x00, 0x00, 0xc3, 0xc3,
+        ];
+        let mut info = match utils::test_buffer("i386:x86-64", &buffer, 0x1000, Endianness::Little)
+        {
+            Some(i) => i,
+            None => return,
+        };
+
+        let mut database = XrefDatabase::default();
+        for instruction in info.disassemble_range(0x1000, 0x100d) {
+            database.add_instruction(&instruction);
+        }
+
+        assert_eq!(database.callers(0x100c), vec![0x1000]);
+        assert_eq!(database.readers(0x101b), vec![0x1005]);
+        assert_eq!(database.references_from(0x1005)[0].kind, XrefKind::Data);
+        assert!(database.references_to(0x2000).is_empty());
+        assert_eq!(database.len(), 2);
+    }
+
+    #[test]
+    fn test_xref_bfd() {
+        use utils;
+
+        let bfd = match utils::open_test_binary() {
+            Some(b) => b,
+            None => return,
+        };
+
+        let database = bfd.cross_references().unwrap();
+        assert!(!database.is_empty());
+
+        // sum_squares() calls square(), and main() calls sum_squares()
+        assert_eq!(database.callers(0x1139), vec![0x1168]);
+        assert_eq!(database.callers(0x1148), vec![0x1198]);
+    }
+}
@@ -1,12 +1,27 @@
 extern crate binutils;
-use binutils::opcodes::DisassembleInfo;
+use binutils::bfd::arch_list;
+use binutils::opcodes::{DisassembleInfo, Endianness};
 use binutils::utils::disassemble_buffer;
 
+// Note: cargo features can leave architectures out of the build
+fn has_arch(arch_name: &str) -> bool {
+    arch_list().iter().any(|arch| arch == arch_name)
+}
+
 #[test]
 fn compact_loop() {
+    if !has_arch("i386") {
+        return;
+    }
+
     // Prepare the disassembler
-    let mut info = disassemble_buffer("i386", &[0xc3, 0x90, 0x66, 0x90], 0x2800)
-        .unwrap_or(DisassembleInfo::empty());
+    let mut info = disassemble_buffer(
+        "i386",
+        &[0xc3, 0x90, 0x66, 0x90],
+        0x2800,
+        Endianness::Little,
+    )
+    .unwrap_or(DisassembleInfo::empty());
 
     // Iterate over the instructions
     loop {
@@ -21,3 +36,76 @@ fn compact_loop() {
         }
     }
 }
+
+fn disassemble_opcodes(arch_name: &str, buffer: &[u8], endian: Endianness) -> Vec<String> {
+    let mut info = disassemble_buffer(arch_name, buffer, 0x1000, endian).unwrap();
+
+    let mut opcodes = Vec::new();
+    while let Some(instruction) = info.disassemble() {
+        opcodes.push(instruction.unwrap().opcode);
+    }
+    opcodes
+}
+
+#[test]
+fn big_endian_mips() {
+    if !has_arch("mips:isa32") {
+        return;
+    }
+
+    // jr ra; nop
+    let buffer = [0x03, 0xe0, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00];
+    assert_eq!(
+        disassemble_opcodes("mips:isa32", &buffer, Endianness::Big),
+        vec!["jr\tra", "nop"]
+    );
+}
+
+#[test]
+fn big_endian_powerpc() {
+    if !has_arch("powerpc:common") {
+        return;
+    }
+
+    // blr
+    let buffer = [0x4e, 0x80, 0x00, 0x20];
+    assert_eq!(
+        disassemble_opcodes("powerpc:common", &buffer, Endianness::Big),
+        vec!["blr"]
+    );
+}
+
+#[test]
+fn big_endian_sparc() {
+    if !has_arch("sparc") {
+        return;
+    }
+
+    // retl; nop
+    let buffer = [0x81, 0xc3, 0xe0, 0x08, 0x01, 0x00, 0x00, 0x00];
+    assert_eq!(
+        disassemble_opcodes("sparc", &buffer, Endianness::Big),
+        vec!["retl", "nop"]
+    );
+}
+
+#[test]
+fn big_endian_arm() {
+    if !has_arch("arm") {
+        return;
+    }
+
+    // bx lr
+    let buffer = [0xe1, 0x2f, 0xff, 0x1e];
+    assert_eq!(
+        disassemble_opcodes("arm", &buffer, Endianness::Big),
+        vec!["bx\tlr"]
+    );
+
+    // BE8 stores instructions in little-endian
+    let buffer = [0x1e, 0xff, 0x2f, 0xe1];
+    assert_eq!(
+        disassemble_opcodes("arm", &buffer, Endianness::Be8),
+        vec!["bx\tlr"]
+    );
+}